@@ -2,7 +2,6 @@ use cloud_pubsub::error;
 use cloud_pubsub::{Client, EncodedMessage, FromPubSubMessage, Subscription};
 use serde_derive::Deserialize;
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::{signal, task};
 
 #[derive(Deserialize)]
@@ -12,6 +11,7 @@ struct Config {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct UpdatePacket(String);
 
 impl FromPubSubMessage for UpdatePacket {
@@ -23,9 +23,12 @@ impl FromPubSubMessage for UpdatePacket {
     }
 }
 
-fn schedule_pubsub_pull(subscription: Arc<Subscription>) {
+fn schedule_pubsub_pull(subscription: Arc<Subscription>) -> task::JoinHandle<()> {
     task::spawn(async move {
-        while subscription.client().is_running() {
+        while subscription
+            .client()
+            .is_ok_and(|client| client.is_running())
+        {
             match subscription.get_messages::<UpdatePacket>(100).await {
                 Ok(messages) => {
                     for (result, ack_id) in messages {
@@ -45,7 +48,7 @@ fn schedule_pubsub_pull(subscription: Arc<Subscription>) {
             }
         }
         println!("No longer pulling");
-    });
+    })
 }
 
 #[tokio::main]
@@ -59,7 +62,7 @@ async fn main() -> Result<(), error::Error> {
 
     let pubsub = match Client::new(config.google_application_credentials).await {
         Err(e) => panic!("Failed to initialize pubsub: {}", e),
-        Ok(mut client) => {
+        Ok(client) => {
             if let Err(e) = client.refresh_token().await {
                 eprintln!("Failed to get token: {}", e);
             } else {
@@ -69,18 +72,18 @@ async fn main() -> Result<(), error::Error> {
         }
     };
 
-    pubsub.spawn_token_renew(Duration::from_secs(15 * 60));
-
-    let topic = Arc::new(pubsub.topic(config.topic));
+    let topic = Arc::new(pubsub.topic(config.topic)?);
     let subscription = topic.subscribe().await?;
     println!("Subscribed to topic with: {}", subscription.name);
     let sub = Arc::new(subscription);
-    schedule_pubsub_pull(Arc::clone(&sub));
+    let puller = schedule_pubsub_pull(Arc::clone(&sub));
     signal::ctrl_c().await?;
     println!("Cleaning up");
-    pubsub.stop();
     println!("Waiting for current Pull to finish....");
-    while Arc::strong_count(&sub) > 1 {}
+    pubsub.shutdown().await;
+    if let Err(e) = puller.await {
+        eprintln!("Pull task failed: {}", e);
+    }
     println!("Deleting subscription");
     if let Ok(s) = Arc::try_unwrap(sub) {
         s.destroy().await?;
@@ -22,7 +22,11 @@ async fn main() {
         Ok(p) => Arc::new(p),
     };
 
-    let topic = Arc::new(pubsub.topic(config.topic.clone()));
+    let topic = Arc::new(
+        pubsub
+            .topic(config.topic.clone())
+            .expect("Failed to create topic"),
+    );
     match topic.clone().publish("🔥").await {
         Ok(response) => {
             println!("{:?}", response);
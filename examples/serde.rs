@@ -11,6 +11,7 @@ struct Config {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct UpdatePacket {
     id: u64,
     name: String,
@@ -39,7 +40,11 @@ async fn main() {
         Ok(p) => p,
     };
 
-    let order_sub = Arc::new(pubsub.subscribe(config.pubsub_subscription));
+    let order_sub = Arc::new(
+        pubsub
+            .subscribe(config.pubsub_subscription)
+            .expect("Failed to create subscription"),
+    );
     match order_sub.clone().get_messages::<UpdatePacket>(100).await {
         Ok(packets) => {
             for packet in &packets {
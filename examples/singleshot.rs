@@ -11,6 +11,7 @@ struct Config {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct UpdatePacket(String);
 
 impl FromPubSubMessage for UpdatePacket {
@@ -36,7 +37,11 @@ async fn main() {
         Ok(p) => p,
     };
 
-    let subscription = Arc::new(pubsub.subscribe(config.pubsub_subscription));
+    let subscription = Arc::new(
+        pubsub
+            .subscribe(config.pubsub_subscription)
+            .expect("Failed to create subscription"),
+    );
     match subscription.get_messages::<UpdatePacket>(100).await {
         Ok(messages) => {
             for (result, ack_id) in messages {
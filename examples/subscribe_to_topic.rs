@@ -9,6 +9,7 @@ struct Config {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct UpdatePacket(String);
 
 impl FromPubSubMessage for UpdatePacket {
@@ -28,7 +29,7 @@ async fn main() {
         .await
         .expect("Failed to initialize pubsub");
 
-    let topic = pubsub.topic(config.topic);
+    let topic = pubsub.topic(config.topic).expect("Failed to create topic");
 
     let sub = topic.subscribe().await.expect("Failed to subscribe");
 
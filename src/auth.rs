@@ -0,0 +1,237 @@
+use crate::client::HyperClient;
+use crate::error;
+use async_trait::async_trait;
+use hyper::body::Buf;
+use serde_derive::Deserialize;
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod authorized_user;
+pub mod external_account;
+pub mod impersonated;
+pub mod metadata;
+pub mod service_account;
+pub mod static_token;
+
+pub use authorized_user::AuthorizedUser;
+pub use external_account::ExternalAccount;
+pub use impersonated::Impersonated;
+pub use metadata::MetadataServer;
+pub use service_account::ServiceAccount;
+pub use static_token::StaticToken;
+
+/// The universe domain of the public Google Cloud.
+pub const DEFAULT_UNIVERSE_DOMAIN: &str = "googleapis.com";
+
+pub(crate) fn default_universe_domain() -> String {
+    String::from(DEFAULT_UNIVERSE_DOMAIN)
+}
+
+/// An OAuth2 access token used to authorize requests to Pub/Sub.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Token {
+    access_token: String,
+    #[serde(default = "default_token_type")]
+    token_type: String,
+    expires_in: Option<u64>,
+}
+
+pub(crate) fn invalid_credentials(err: impl std::fmt::Display) -> error::Error {
+    error::Error::InvalidCredentials(err.to_string())
+}
+
+fn default_token_type() -> String {
+    String::from("Bearer")
+}
+
+impl Token {
+    pub fn new(access_token: String, token_type: String, expires_in: Option<Duration>) -> Self {
+        Token {
+            access_token,
+            token_type,
+            expires_in: expires_in.map(|d| d.as_secs()),
+        }
+    }
+
+    pub fn access_token(&self) -> &str {
+        &self.access_token
+    }
+
+    pub fn token_type(&self) -> &str {
+        &self.token_type
+    }
+
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_in.map(Duration::from_secs)
+    }
+}
+
+impl From<goauth::auth::Token> for Token {
+    fn from(token: goauth::auth::Token) -> Self {
+        Token {
+            access_token: token.access_token().to_string(),
+            token_type: token.token_type().to_string(),
+            expires_in: Some(u64::from(token.expires_in())),
+        }
+    }
+}
+
+/// A source of access tokens for a `Client`.
+///
+/// Implement this to plug in secret managers or test doubles.
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// Fetches a fresh token, using `client` for any HTTP exchange.
+    async fn token(&self, client: &HyperClient) -> Result<Token, error::Error>;
+
+    /// The project the credentials belong to, if known.
+    async fn project(&self, _client: &HyperClient) -> Result<Option<String>, error::Error> {
+        Ok(None)
+    }
+
+    /// The universe domain the credentials are valid in.
+    async fn universe_domain(&self, _client: &HyperClient) -> Result<String, error::Error> {
+        Ok(default_universe_domain())
+    }
+}
+
+#[derive(Deserialize)]
+struct CredentialsFile {
+    #[serde(rename = "type")]
+    credentials_type: String,
+}
+
+/// Builds a provider from a credentials JSON file, dispatching on its `type`.
+pub fn from_json(contents: String) -> Result<Arc<dyn TokenProvider>, error::Error> {
+    let file: CredentialsFile = serde_json::from_str(&contents)?;
+    match file.credentials_type.as_str() {
+        "service_account" => Ok(Arc::new(contents.parse::<ServiceAccount>()?)),
+        "authorized_user" => Ok(Arc::new(contents.parse::<AuthorizedUser>()?)),
+        "external_account" => contents.parse::<ExternalAccount>()?.into_provider(),
+        "impersonated_service_account" => {
+            let file: impersonated::ImpersonatedFile = serde_json::from_str(&contents)?;
+            Ok(Arc::new(Impersonated::try_from(file)?))
+        }
+        other => Err(invalid_credentials(format!(
+            "Unsupported credentials type: {}",
+            other
+        ))),
+    }
+}
+
+/// Walks the Application Default Credentials chain.
+///
+/// Credentials are looked up in order from the file named by
+/// `GOOGLE_APPLICATION_CREDENTIALS`, the well-known file written by
+/// `gcloud auth application-default login`, and finally the GCE metadata server.
+pub fn default_provider() -> Result<Arc<dyn TokenProvider>, error::Error> {
+    match credentials_path(|name| env::var_os(name)) {
+        Some(path) => {
+            log::debug!("Loading credentials from {}", path.display());
+            from_json(fs::read_to_string(path)?)
+        }
+        None => {
+            log::debug!("Loading credentials from the metadata server");
+            Ok(Arc::new(MetadataServer::new()))
+        }
+    }
+}
+
+/// The credentials file to load, looking up environment variables with `var`, or `None`
+/// when the metadata server should be used.
+fn credentials_path(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    if let Some(path) = var("GOOGLE_APPLICATION_CREDENTIALS") {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_dir = match var("CLOUDSDK_CONFIG") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(var("APPDATA")?).join("gcloud"),
+        None => PathBuf::from(var("HOME")?).join(".config").join("gcloud"),
+    };
+    Some(config_dir.join("application_default_credentials.json")).filter(|path| path.is_file())
+}
+
+pub(crate) async fn fetch_token(
+    client: &HyperClient,
+    request: hyper::Request<hyper::Body>,
+) -> Result<Token, error::Error> {
+    let response = client.request(request).await?;
+    let status = response.status();
+    let body = hyper::body::aggregate(response).await?;
+    if status.is_success() {
+        Ok(serde_json::from_reader(body.reader())?)
+    } else {
+        let err: goauth::auth::TokenErr = serde_json::from_reader(body.reader())?;
+        Err(goauth::GoErr::from(err).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// A fresh config directory, optionally holding an `application_default_credentials.json`.
+    fn config_dir(name: &str, with_credentials: bool) -> PathBuf {
+        let dir = env::temp_dir().join(format!("cloud-pubsub-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        if with_credentials {
+            fs::write(dir.join("application_default_credentials.json"), "{}").unwrap();
+        }
+        dir
+    }
+
+    fn lookup(vars: &[(&str, &PathBuf)]) -> Option<PathBuf> {
+        let vars: HashMap<_, _> = vars.iter().cloned().collect();
+        credentials_path(|name| vars.get(name).map(|value| value.as_os_str().to_owned()))
+    }
+
+    #[test]
+    fn application_credentials_variable_comes_first() {
+        let config = config_dir("adc-first", true);
+        let key = PathBuf::from("/keys/service-account.json");
+        assert_eq!(
+            lookup(&[
+                ("GOOGLE_APPLICATION_CREDENTIALS", &key),
+                ("CLOUDSDK_CONFIG", &config)
+            ]),
+            Some(key)
+        );
+    }
+
+    #[test]
+    fn gcloud_well_known_file_comes_second() {
+        let config = config_dir("adc-gcloud", true);
+        assert_eq!(
+            lookup(&[("CLOUDSDK_CONFIG", &config)]),
+            Some(config.join("application_default_credentials.json"))
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn gcloud_config_defaults_to_home() {
+        let home = config_dir("adc-home", false);
+        let config = home.join(".config").join("gcloud");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(config.join("application_default_credentials.json"), "{}").unwrap();
+        assert_eq!(
+            lookup(&[("HOME", &home)]),
+            Some(config.join("application_default_credentials.json"))
+        );
+    }
+
+    #[test]
+    fn metadata_server_comes_last() {
+        let config = config_dir("adc-metadata", false);
+        assert_eq!(lookup(&[("CLOUDSDK_CONFIG", &config)]), None);
+        assert_eq!(lookup(&[]), None);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::auth::{fetch_token, invalid_credentials, Token, TokenProvider};
+use crate::client::HyperClient;
+use crate::error;
+use async_trait::async_trait;
+use serde_derive::Deserialize;
+use std::str::FromStr;
+
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Exchanges a user refresh token, as written by `gcloud auth application-default login`.
+#[derive(Deserialize)]
+pub struct AuthorizedUser {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+    quota_project_id: Option<String>,
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+}
+
+fn default_token_uri() -> String {
+    String::from(DEFAULT_TOKEN_URI)
+}
+
+impl AuthorizedUser {
+    pub fn new(client_id: String, client_secret: String, refresh_token: String) -> Self {
+        AuthorizedUser {
+            client_id,
+            client_secret,
+            refresh_token,
+            quota_project_id: None,
+            token_uri: default_token_uri(),
+        }
+    }
+
+    /// Overrides the endpoint the refresh token is exchanged at.
+    pub fn with_token_uri(mut self, token_uri: String) -> Self {
+        self.token_uri = token_uri;
+        self
+    }
+
+    pub fn with_project(mut self, project: String) -> Self {
+        self.quota_project_id = Some(project);
+        self
+    }
+}
+
+impl FromStr for AuthorizedUser {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for AuthorizedUser {
+    async fn token(&self, client: &HyperClient) -> Result<Token, error::Error> {
+        let form = serde_urlencoded::to_string([
+            ("grant_type", "refresh_token"),
+            ("client_id", &self.client_id),
+            ("client_secret", &self.client_secret),
+            ("refresh_token", &self.refresh_token),
+        ])
+        .map_err(invalid_credentials)?;
+        let request = hyper::Request::post(&self.token_uri)
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(hyper::Body::from(form))
+            .map_err(invalid_credentials)?;
+        fetch_token(client, request).await
+    }
+
+    async fn project(&self, _client: &HyperClient) -> Result<Option<String>, error::Error> {
+        Ok(self.quota_project_id.clone())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::auth::{
+    default_universe_domain, fetch_token, invalid_credentials, Impersonated, Token, TokenProvider,
+};
+use crate::client::HyperClient;
+use crate::error;
+use async_trait::async_trait;
+use hyper::body::Buf;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::sync::Arc;
+
+const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// Where the subject token handed to STS is read from.
+#[derive(Deserialize, Clone)]
+pub struct CredentialSource {
+    file: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    format: CredentialFormat,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum CredentialFormat {
+    #[default]
+    Text,
+    Json {
+        subject_token_field_name: String,
+    },
+}
+
+/// Workload identity federation credentials, as found in `external_account` files.
+///
+/// A subject token from a file or URL is exchanged at the STS endpoint for a Google
+/// access token. Files that name a `service_account_impersonation_url` are loaded by
+/// `auth::from_json` as this provider wrapped in `Impersonated`.
+#[derive(Deserialize, Clone)]
+pub struct ExternalAccount {
+    audience: String,
+    subject_token_type: String,
+    token_url: String,
+    credential_source: CredentialSource,
+    service_account_impersonation_url: Option<String>,
+    quota_project_id: Option<String>,
+    #[serde(default = "default_universe_domain")]
+    universe_domain: String,
+}
+
+impl ExternalAccount {
+    /// Overrides the STS endpoint, e.g. for a local mock.
+    pub fn with_token_url(mut self, token_url: String) -> Self {
+        self.token_url = token_url;
+        self
+    }
+
+    /// Wraps this provider in `Impersonated` when the file names an impersonation URL.
+    pub fn into_provider(self) -> Result<Arc<dyn TokenProvider>, error::Error> {
+        match self.service_account_impersonation_url.clone() {
+            Some(url) => Ok(Arc::new(Impersonated::from_url(Arc::new(self), &url)?)),
+            None => Ok(Arc::new(self)),
+        }
+    }
+
+    async fn subject_token(&self, client: &HyperClient) -> Result<String, error::Error> {
+        let source = &self.credential_source;
+        let raw = match (&source.file, &source.url) {
+            (Some(path), _) => fs::read_to_string(path)?,
+            (None, Some(url)) => {
+                let mut request = hyper::Request::get(url);
+                for (name, value) in &source.headers {
+                    request = request.header(name.as_str(), value.as_str());
+                }
+                let response = client
+                    .request(
+                        request
+                            .body(hyper::Body::empty())
+                            .map_err(invalid_credentials)?,
+                    )
+                    .await?;
+                let status = response.status();
+                let body = hyper::body::aggregate(response).await?;
+                let mut raw = String::new();
+                body.reader().read_to_string(&mut raw)?;
+                if !status.is_success() {
+                    return Err(io::Error::other(format!(
+                        "Failed to fetch subject token ({}): {}",
+                        status, raw
+                    ))
+                    .into());
+                }
+                raw
+            }
+            (None, None) => {
+                return Err(invalid_credentials(
+                    "credential_source must contain a file or url",
+                ))
+            }
+        };
+
+        match &source.format {
+            CredentialFormat::Text => Ok(raw.trim().to_string()),
+            CredentialFormat::Json {
+                subject_token_field_name,
+            } => {
+                let json: serde_json::Value = serde_json::from_str(&raw)?;
+                json.get(subject_token_field_name)
+                    .and_then(|token| token.as_str())
+                    .map(String::from)
+                    .ok_or_else(|| {
+                        invalid_credentials(format!(
+                            "Subject token is missing {}",
+                            subject_token_field_name
+                        ))
+                    })
+            }
+        }
+    }
+}
+
+impl FromStr for ExternalAccount {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ExternalAccount {
+    async fn token(&self, client: &HyperClient) -> Result<Token, error::Error> {
+        let subject_token = self.subject_token(client).await?;
+        let form = serde_urlencoded::to_string([
+            ("grant_type", TOKEN_EXCHANGE_GRANT_TYPE),
+            ("audience", &self.audience),
+            ("scope", CLOUD_PLATFORM_SCOPE),
+            ("requested_token_type", ACCESS_TOKEN_TYPE),
+            ("subject_token", &subject_token),
+            ("subject_token_type", &self.subject_token_type),
+        ])
+        .map_err(invalid_credentials)?;
+        let request = hyper::Request::post(&self.token_url)
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(hyper::Body::from(form))
+            .map_err(invalid_credentials)?;
+        fetch_token(client, request).await
+    }
+
+    async fn project(&self, _client: &HyperClient) -> Result<Option<String>, error::Error> {
+        Ok(self.quota_project_id.clone())
+    }
+
+    async fn universe_domain(&self, _client: &HyperClient) -> Result<String, error::Error> {
+        Ok(self.universe_domain.clone())
+    }
+}
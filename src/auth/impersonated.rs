@@ -0,0 +1,183 @@
+use crate::auth::{invalid_credentials, ServiceAccount, Token, TokenProvider};
+use crate::client::HyperClient;
+use crate::error;
+use async_trait::async_trait;
+use goauth::scopes::Scope;
+use hyper::body::Buf;
+use serde_derive::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_IAM_ENDPOINT: &str = "https://iamcredentials.googleapis.com";
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(3600);
+const PUBSUB_SCOPE: &str = "https://www.googleapis.com/auth/pubsub";
+
+#[derive(Serialize)]
+struct GenerateAccessTokenRequest<'a> {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    delegates: Vec<String>,
+    scope: [&'a str; 1],
+    lifetime: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateAccessTokenResponse {
+    access_token: String,
+}
+
+/// The `impersonated_service_account` file written by
+/// `gcloud auth application-default login --impersonate-service-account`.
+#[derive(Deserialize)]
+pub(crate) struct ImpersonatedFile {
+    service_account_impersonation_url: String,
+    source_credentials: serde_json::Value,
+    #[serde(default)]
+    delegates: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: error::Error,
+}
+
+/// Impersonates a service account through the IAM Credentials `generateAccessToken` API.
+///
+/// The base provider must hold a token with the `cloud-platform` scope and the
+/// `roles/iam.serviceAccountTokenCreator` role on the target (or on each delegate).
+pub struct Impersonated {
+    base: Arc<dyn TokenProvider>,
+    target_principal: String,
+    delegates: Vec<String>,
+    lifetime: Duration,
+    endpoint: String,
+}
+
+impl Impersonated {
+    pub fn new(base: Arc<dyn TokenProvider>, target_principal: String) -> Self {
+        Impersonated {
+            base,
+            target_principal,
+            delegates: Vec::new(),
+            lifetime: DEFAULT_LIFETIME,
+            endpoint: String::from(DEFAULT_IAM_ENDPOINT),
+        }
+    }
+
+    /// Creates a provider from a full `generateAccessToken` URL, as found in credential
+    /// files. The endpoint is taken from the URL.
+    pub fn from_url(base: Arc<dyn TokenProvider>, url: &str) -> Result<Self, error::Error> {
+        let (endpoint, target) = url
+            .trim_end_matches(":generateAccessToken")
+            .rsplit_once("/v1/projects/-/serviceAccounts/")
+            .ok_or_else(|| {
+                invalid_credentials(format!(
+                    "Invalid service account impersonation URL: {}",
+                    url
+                ))
+            })?;
+        Ok(Impersonated::new(base, target.to_string()).with_endpoint(endpoint.to_string()))
+    }
+
+    /// Sets the chain of service accounts to delegate through, in order.
+    pub fn with_delegates(mut self, delegates: Vec<String>) -> Self {
+        self.delegates = delegates;
+        self
+    }
+
+    /// Sets the requested token lifetime. Lifetimes over an hour require an org policy.
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Overrides the IAM Credentials endpoint, e.g. for a local fake.
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+}
+
+impl TryFrom<ImpersonatedFile> for Impersonated {
+    type Error = error::Error;
+
+    fn try_from(file: ImpersonatedFile) -> Result<Self, Self::Error> {
+        let source_credentials = file.source_credentials.to_string();
+        // `generateAccessToken` requires the cloud-platform scope on the caller's token
+        let base: Arc<dyn TokenProvider> =
+            match file.source_credentials.get("type").and_then(|t| t.as_str()) {
+                Some("service_account") => Arc::new(
+                    source_credentials
+                        .parse::<ServiceAccount>()?
+                        .with_scope(Scope::CloudPlatform),
+                ),
+                _ => crate::auth::from_json(source_credentials)?,
+            };
+        let delegates = file
+            .delegates
+            .iter()
+            .map(|delegate| {
+                delegate
+                    .trim_start_matches("projects/-/serviceAccounts/")
+                    .to_string()
+            })
+            .collect();
+
+        Ok(
+            Impersonated::from_url(base, &file.service_account_impersonation_url)?
+                .with_delegates(delegates),
+        )
+    }
+}
+
+#[async_trait]
+impl TokenProvider for Impersonated {
+    async fn token(&self, client: &HyperClient) -> Result<Token, error::Error> {
+        let base_token = self.base.token(client).await?;
+        let payload = GenerateAccessTokenRequest {
+            delegates: self
+                .delegates
+                .iter()
+                .map(|delegate| format!("projects/-/serviceAccounts/{}", delegate))
+                .collect(),
+            scope: [PUBSUB_SCOPE],
+            lifetime: format!("{}s", self.lifetime.as_secs()),
+        };
+
+        let request = hyper::Request::post(format!(
+            "{}/v1/projects/-/serviceAccounts/{}:generateAccessToken",
+            self.endpoint, self.target_principal
+        ))
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header(
+            hyper::header::AUTHORIZATION,
+            format!("{} {}", base_token.token_type(), base_token.access_token()),
+        )
+        .body(hyper::Body::from(serde_json::to_string(&payload)?))
+        .map_err(invalid_credentials)?;
+
+        let response = client.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::aggregate(response).await?;
+        if !status.is_success() {
+            let response: ErrorResponse = serde_json::from_reader(body.reader())?;
+            return Err(response.error);
+        }
+
+        let response: GenerateAccessTokenResponse = serde_json::from_reader(body.reader())?;
+        Ok(Token::new(
+            response.access_token,
+            String::from("Bearer"),
+            Some(self.lifetime),
+        ))
+    }
+
+    async fn project(&self, client: &HyperClient) -> Result<Option<String>, error::Error> {
+        self.base.project(client).await
+    }
+
+    async fn universe_domain(&self, client: &HyperClient) -> Result<String, error::Error> {
+        self.base.universe_domain(client).await
+    }
+}
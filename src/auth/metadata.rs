@@ -0,0 +1,86 @@
+use crate::auth::{
+    default_universe_domain, fetch_token, invalid_credentials, Token, TokenProvider,
+};
+use crate::client::HyperClient;
+use crate::error;
+use async_trait::async_trait;
+use hyper::body::Buf;
+use std::env;
+use std::io::Read;
+
+const DEFAULT_METADATA_HOST: &str = "metadata.google.internal";
+
+/// Fetches tokens for the default service account from the GCE/GKE metadata server.
+///
+/// The host defaults to `GCE_METADATA_HOST` when set, and `metadata.google.internal`
+/// otherwise.
+pub struct MetadataServer {
+    host: String,
+}
+
+impl Default for MetadataServer {
+    fn default() -> Self {
+        MetadataServer {
+            host: env::var("GCE_METADATA_HOST")
+                .unwrap_or_else(|_| String::from(DEFAULT_METADATA_HOST)),
+        }
+    }
+}
+
+impl MetadataServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides the metadata server host, e.g. `127.0.0.1:8080` for a local stand-in.
+    pub fn with_host(mut self, host: String) -> Self {
+        self.host = host;
+        self
+    }
+
+    async fn get_value(
+        &self,
+        client: &HyperClient,
+        path: &str,
+    ) -> Result<Option<String>, error::Error> {
+        let response = client.request(self.request(path)?).await?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        let body = hyper::body::aggregate(response).await?;
+        let mut value = String::new();
+        body.reader().read_to_string(&mut value)?;
+        Ok(Some(value.trim().to_string()))
+    }
+
+    fn request(&self, path: &str) -> Result<hyper::Request<hyper::Body>, error::Error> {
+        hyper::Request::get(format!("http://{}/computeMetadata/v1/{}", self.host, path))
+            .header("Metadata-Flavor", "Google")
+            .body(hyper::Body::empty())
+            .map_err(|e| invalid_credentials(format!("Invalid metadata host: {}", e)))
+    }
+}
+
+#[async_trait]
+impl TokenProvider for MetadataServer {
+    async fn token(&self, client: &HyperClient) -> Result<Token, error::Error> {
+        let request = self.request("instance/service-accounts/default/token")?;
+        fetch_token(client, request).await
+    }
+
+    async fn project(&self, client: &HyperClient) -> Result<Option<String>, error::Error> {
+        let project = self.get_value(client, "project/project-id").await?;
+        if project.is_none() {
+            log::warn!("Metadata server did not return a project id");
+        }
+        Ok(project)
+    }
+
+    async fn universe_domain(&self, client: &HyperClient) -> Result<String, error::Error> {
+        Ok(self
+            .get_value(client, "universe/universe-domain")
+            .await?
+            .filter(|universe_domain| !universe_domain.is_empty())
+            .unwrap_or_else(default_universe_domain))
+    }
+}
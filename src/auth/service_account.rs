@@ -0,0 +1,198 @@
+use crate::auth::{
+    default_universe_domain, fetch_token, invalid_credentials, Token, TokenProvider,
+    DEFAULT_UNIVERSE_DOMAIN,
+};
+use crate::client::HyperClient;
+use crate::error;
+use async_trait::async_trait;
+use base64::Engine;
+use goauth::auth::JwtClaims;
+use goauth::credentials::Credentials;
+use goauth::scopes::Scope;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde_derive::{Deserialize, Serialize};
+use smpl_jwt::Jwt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+const SELF_SIGNED_JWT_LIFETIME: Duration = Duration::from_secs(3600);
+
+#[derive(Deserialize)]
+struct UniverseDomain {
+    #[serde(default = "default_universe_domain")]
+    universe_domain: String,
+}
+
+/// The key fields of a service account key file.
+#[derive(Deserialize)]
+struct PrivateKey {
+    private_key_id: String,
+    private_key: String,
+}
+
+#[derive(Serialize)]
+struct JwtHeader<'a> {
+    alg: &'a str,
+    typ: &'a str,
+    /// The ID of the signing key, which Google requires for self-signed JWTs.
+    kid: &'a str,
+}
+
+#[derive(Serialize)]
+struct SelfSignedClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    iat: u64,
+    exp: u64,
+}
+
+/// Authenticates with a service account key.
+///
+/// By default a signed JWT is exchanged for an access token at the key's token endpoint.
+/// With `with_self_signed_jwt` the signed JWT is sent to Pub/Sub directly instead. Keys
+/// from a universe domain other than `googleapis.com` always use self-signed JWTs.
+pub struct ServiceAccount {
+    credentials: Credentials,
+    scope: Scope,
+    universe_domain: String,
+    self_signed_jwt: bool,
+    self_signed_audience: Option<String>,
+}
+
+impl ServiceAccount {
+    pub fn new(credentials: Credentials) -> Self {
+        ServiceAccount {
+            credentials,
+            scope: Scope::PubSub,
+            universe_domain: default_universe_domain(),
+            self_signed_jwt: false,
+            self_signed_audience: None,
+        }
+    }
+
+    /// Uses a self-signed JWT with the Pub/Sub service of the key's universe domain as
+    /// audience, skipping the token endpoint round trip.
+    pub fn with_self_signed_jwt(mut self) -> Self {
+        self.self_signed_jwt = true;
+        self
+    }
+
+    /// Uses a self-signed JWT with a custom audience.
+    pub fn with_self_signed_audience(mut self, audience: String) -> Self {
+        self.self_signed_jwt = true;
+        self.self_signed_audience = Some(audience);
+        self
+    }
+
+    /// Requests a different scope than `pubsub`, e.g. `CloudPlatform` when the token is
+    /// used as the base of an `Impersonated` provider.
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    fn self_signed_token(&self, audience: &str) -> Result<Token, error::Error> {
+        let iat = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let claims = SelfSignedClaims {
+            iss: self.credentials.iss(),
+            sub: self.credentials.iss(),
+            aud: audience.to_string(),
+            iat,
+            exp: iat + SELF_SIGNED_JWT_LIFETIME.as_secs(),
+        };
+        let key: PrivateKey = serde_json::from_value(serde_json::to_value(&self.credentials)?)?;
+        let header = JwtHeader {
+            alg: "RS256",
+            typ: "JWT",
+            kid: &key.private_key_id,
+        };
+        Ok(Token::new(
+            sign_jwt(&header, &claims, &key.private_key)?,
+            String::from("Bearer"),
+            Some(SELF_SIGNED_JWT_LIFETIME),
+        ))
+    }
+}
+
+/// Signs a JWT with RS256. `smpl_jwt` cannot set the `kid` header, so this is done by hand.
+fn sign_jwt<H: serde::Serialize, C: serde::Serialize>(
+    header: &H,
+    claims: &C,
+    private_key: &str,
+) -> Result<String, error::Error> {
+    let engine = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let input = format!(
+        "{}.{}",
+        engine.encode(serde_json::to_vec(header)?),
+        engine.encode(serde_json::to_vec(claims)?)
+    );
+    let key = PKey::private_key_from_pem(private_key.as_bytes()).map_err(invalid_credentials)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(invalid_credentials)?;
+    signer
+        .update(input.as_bytes())
+        .map_err(invalid_credentials)?;
+    let signature = signer.sign_to_vec().map_err(invalid_credentials)?;
+    Ok(format!("{}.{}", input, engine.encode(signature)))
+}
+
+impl FromStr for ServiceAccount {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let UniverseDomain { universe_domain } = serde_json::from_str(s)?;
+        let mut service_account = ServiceAccount::new(Credentials::from_str(s)?);
+        service_account.universe_domain = universe_domain;
+        Ok(service_account)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ServiceAccount {
+    async fn token(&self, client: &HyperClient) -> Result<Token, error::Error> {
+        if self.self_signed_jwt || self.universe_domain != DEFAULT_UNIVERSE_DOMAIN {
+            let audience = self
+                .self_signed_audience
+                .clone()
+                .unwrap_or_else(|| format!("https://pubsub.{}/", self.universe_domain));
+            return self.self_signed_token(&audience);
+        }
+
+        let claims = JwtClaims::new(
+            self.credentials.iss(),
+            &self.scope,
+            self.credentials.token_uri(),
+            None,
+            None,
+        );
+        let jwt = Jwt::new(claims, self.credentials.rsa_key()?, None);
+        let assertion = jwt.finalize().map_err(goauth::GoErr::from)?;
+        let form = serde_urlencoded::to_string([
+            ("grant_type", JWT_BEARER_GRANT_TYPE),
+            ("assertion", &assertion),
+        ])
+        .map_err(invalid_credentials)?;
+        let request = hyper::Request::post(self.credentials.token_uri())
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(hyper::Body::from(form))
+            .map_err(invalid_credentials)?;
+        fetch_token(client, request).await
+    }
+
+    async fn project(&self, _client: &HyperClient) -> Result<Option<String>, error::Error> {
+        Ok(Some(self.credentials.project()))
+    }
+
+    async fn universe_domain(&self, _client: &HyperClient) -> Result<String, error::Error> {
+        Ok(self.universe_domain.clone())
+    }
+}
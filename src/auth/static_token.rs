@@ -0,0 +1,37 @@
+use crate::auth::{Token, TokenProvider};
+use crate::client::HyperClient;
+use crate::error;
+use async_trait::async_trait;
+
+/// Hands out the same token on every refresh.
+///
+/// Useful for tokens minted elsewhere and for tests.
+pub struct StaticToken {
+    token: Token,
+    project: Option<String>,
+}
+
+impl StaticToken {
+    pub fn new(token: Token) -> Self {
+        StaticToken {
+            token,
+            project: None,
+        }
+    }
+
+    pub fn with_project(mut self, project: String) -> Self {
+        self.project = Some(project);
+        self
+    }
+}
+
+#[async_trait]
+impl TokenProvider for StaticToken {
+    async fn token(&self, _client: &HyperClient) -> Result<Token, error::Error> {
+        Ok(self.token.clone())
+    }
+
+    async fn project(&self, _client: &HyperClient) -> Result<Option<String>, error::Error> {
+        Ok(self.project.clone())
+    }
+}
@@ -0,0 +1,57 @@
+//! Prints the changes needed to bring a project in line with a manifest, and makes them
+//! with `--apply`.
+//!
+//! Credentials and the project are found like `Client::from_default_credentials` does,
+//! so setting `PUBSUB_EMULATOR_HOST` and `GOOGLE_CLOUD_PROJECT` targets an emulator.
+
+use cloud_pubsub::reconcile::Manifest;
+use cloud_pubsub::{error, Client};
+use std::env;
+use std::process;
+
+const USAGE: &str = "Usage: pubsub-reconcile [--apply] <manifest.{json,yaml,toml}>";
+
+#[tokio::main]
+async fn main() {
+    let mut apply = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--apply" => apply = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&path, apply).await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(path: &str, apply: bool) -> Result<(), error::Error> {
+    let manifest = Manifest::from_path(path)?;
+    let pubsub = Client::from_default_credentials().await?;
+
+    let plan = pubsub.plan(&manifest).await?;
+    print!("{}", plan);
+    if apply && !plan.is_empty() {
+        pubsub.apply(&plan).await?;
+        println!("Applied.");
+    }
+    Ok(())
+}
@@ -1,45 +1,110 @@
+use crate::auth::{self, Token, TokenProvider, DEFAULT_UNIVERSE_DOMAIN};
+use crate::connector::{self, HttpsConnector, TlsOptions};
 use crate::error;
-use crate::subscription::Subscription;
-use crate::topic::Topic;
-use goauth::auth::JwtClaims;
-use goauth::scopes::Scope;
-use hyper::client::HttpConnector;
-use hyper_tls::HttpsConnector;
-use smpl_jwt::Jwt;
+use crate::name::{IntoResourceName, SubscriptionName, TopicName};
+use crate::proxy::Proxy;
+use crate::retry::{Operation, RetryPolicy};
+use crate::subscription::{Subscription, SubscriptionConfig};
+use crate::topic::{Topic, TopicConfig};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::mem;
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Mutex};
 use tokio::task;
 use tokio::time;
 
-type HyperClient = Arc<hyper::Client<HttpsConnector<HttpConnector>, hyper::Body>>;
+/// Tokens are refreshed this long before they expire, or halfway through their lifetime
+/// when that is shorter.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// How long an operation may take, including token refreshes, when neither the client nor
+/// the call sets a timeout.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+const DEFAULT_ENDPOINT: &str = "https://pubsub.googleapis.com";
+const DEFAULT_USER_AGENT: &str = concat!("cloud-pubsub/", env!("CARGO_PKG_VERSION"));
+
+pub type HyperClient = Arc<hyper::Client<HttpsConnector, hyper::Body>>;
 
 pub struct State {
-    token: Option<goauth::auth::Token>,
-    credentials_string: String,
+    token: Option<Token>,
+    token_refresh_at: Option<Instant>,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    refresh_lock: Arc<Mutex<()>>,
+    endpoint: String,
+    universe_domain: String,
+    user_agent: String,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
     project: Option<String>,
     hyper_client: HyperClient,
-    running: Arc<AtomicBool>,
+    stopped: Arc<watch::Sender<bool>>,
+    in_flight: Arc<watch::Sender<usize>>,
+    tasks: Vec<task::JoinHandle<()>>,
 }
 
 impl State {
-    pub fn token_type(&self) -> &str {
-        self.token.as_ref().unwrap().token_type()
+    /// The value of the `Authorization` header for the current token.
+    fn authorization(&self) -> Option<String> {
+        self.token
+            .as_ref()
+            .map(|token| format!("{} {}", token.token_type(), token.access_token()))
     }
 
-    pub fn access_token(&self) -> &str {
-        self.token.as_ref().unwrap().access_token()
+    fn token_is_fresh(&self) -> bool {
+        self.token_provider.is_none()
+            || self.token.is_some()
+                && self
+                    .token_refresh_at
+                    .is_none_or(|refresh_at| Instant::now() < refresh_at)
     }
 
-    pub fn project(&self) -> &str {
-        &(self.project.as_ref().expect("Google Cloud Project has not been set. If it is not in your credential file, call set_project to set it manually."))
+    pub fn project(&self) -> Result<&str, error::Error> {
+        self.project.as_deref().ok_or(error::Error::MissingProject)
     }
 }
 
 pub struct Client(Arc<RwLock<State>>);
 
+/// One page of a list call. Pass `next_page_token` to the next call to continue.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    #[serde(default = "Vec::new", alias = "topics", alias = "subscriptions")]
+    pub items: Vec<T>,
+    #[serde(default)]
+    pub next_page_token: Option<String>,
+}
+
+/// Counts an operation as in flight until dropped.
+struct InFlight(Arc<watch::Sender<usize>>);
+
+impl InFlight {
+    fn new(counter: Arc<watch::Sender<usize>>) -> Self {
+        counter.send_modify(|n| *n += 1);
+        InFlight(counter)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.send_modify(|n| *n -= 1);
+    }
+}
+
+/// Per-call overrides of the client's defaults, carried by `Topic` and `Subscription`.
+#[derive(Clone, Default)]
+pub(crate) struct CallOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+}
+
 impl Clone for Client {
     fn clone(&self) -> Self {
         Client(self.0.clone())
@@ -47,116 +112,275 @@ impl Clone for Client {
 }
 
 impl Client {
+    /// Creates a client from the contents of a credentials JSON file.
+    ///
+    /// Both service account keys and `authorized_user` files, as written by
+    /// `gcloud auth application-default login`, are accepted.
     pub async fn from_string(credentials_string: String) -> Result<Self, error::Error> {
-        let mut client = Client(Arc::new(RwLock::new(State {
-            token: None,
-            credentials_string,
-            project: None,
-            hyper_client: setup_hyper(),
-            running: Arc::new(AtomicBool::new(true)),
-        })));
+        Self::from_token_provider(auth::from_json(credentials_string)?).await
+    }
 
-        match client.refresh_token().await {
-            Ok(_) => Ok(client),
-            Err(e) => Err(e),
-        }
+    /// Creates a client from a service account key which authenticates with self-signed
+    /// JWTs rather than exchanging them at the OAuth token endpoint.
+    pub async fn from_self_signed_jwt(credentials_string: String) -> Result<Self, error::Error> {
+        let service_account: auth::ServiceAccount = credentials_string.parse()?;
+        Self::from_token_provider(Arc::new(service_account.with_self_signed_jwt())).await
     }
 
     pub async fn new(credentials_path: String) -> Result<Self, error::Error> {
-        let credentials_string = fs::read_to_string(credentials_path).unwrap();
+        let credentials_string = fs::read_to_string(credentials_path)?;
         Self::from_string(credentials_string).await
     }
 
-    pub fn subscribe(&self, name: String) -> Subscription {
-        Subscription {
+    /// Creates a client using Application Default Credentials.
+    ///
+    /// See `auth::default_provider` for the discovery order.
+    /// When `PUBSUB_EMULATOR_HOST` is set an unauthenticated emulator client is returned
+    /// instead, with the project taken from `GOOGLE_CLOUD_PROJECT` when it is set.
+    pub async fn from_default_credentials() -> Result<Self, error::Error> {
+        if let Ok(host) = env::var("PUBSUB_EMULATOR_HOST") {
+            return emulator_client(host, project_from_env());
+        }
+        Self::from_token_provider(auth::default_provider()?).await
+    }
+
+    /// Creates a client for the Pub/Sub emulator at `host`, e.g. `localhost:8085`.
+    ///
+    /// Requests are sent over plain HTTP without any authentication. An empty `project`
+    /// returns `Error::MissingProject`.
+    pub fn emulator(host: String, project: String) -> Result<Self, error::Error> {
+        if project.is_empty() {
+            return Err(error::Error::MissingProject);
+        }
+        ClientBuilder::new()
+            .emulator(host)
+            .project(project)
+            .build_client()
+    }
+
+    /// Creates a client for workloads on GCE or GKE, using the metadata server for both
+    /// the access token and the project.
+    pub async fn from_metadata_server() -> Result<Self, error::Error> {
+        Self::from_token_provider(Arc::new(auth::MetadataServer::new())).await
+    }
+
+    /// Creates a client which authorizes requests with tokens from `token_provider`.
+    ///
+    /// The project is taken from the provider when it knows one, e.g. from a key file or
+    /// the metadata server, and falls back to `GOOGLE_CLOUD_PROJECT`. Requests go to the
+    /// emulator named by `PUBSUB_EMULATOR_HOST` when it is set.
+    pub async fn from_token_provider(
+        token_provider: Arc<dyn TokenProvider>,
+    ) -> Result<Self, error::Error> {
+        ClientBuilder::new()
+            .token_provider(token_provider)
+            .build()
+            .await
+    }
+
+    /// Starts configuring a client with a `ClientBuilder`.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// A handle to an existing subscription, by ID in the client's project or by full name.
+    pub fn subscribe<N: IntoResourceName<SubscriptionName>>(
+        &self,
+        name: N,
+    ) -> Result<Subscription, error::Error> {
+        Ok(Subscription {
             client: Some(self.clone()),
-            name: format!("projects/{}/subscriptions/{}", self.project(), name),
+            name: name.into_resource_name(self)?,
             topic: None,
-        }
+            config: SubscriptionConfig::default(),
+            options: CallOptions::default(),
+        })
     }
 
     pub fn set_project(&mut self, project: String) {
         self.0.write().unwrap().project = Some(project);
     }
 
-    pub fn project(&self) -> String {
-        self.0.read().unwrap().project().to_string()
+    /// The project topics and subscriptions are created in, or `Error::MissingProject` if
+    /// neither the credentials nor the builder provided one.
+    pub fn project(&self) -> Result<String, error::Error> {
+        Ok(self.0.read().unwrap().project()?.to_string())
     }
 
-    pub fn topic(&self, name: String) -> Topic {
-        Topic {
-            client: Some(Client(self.0.clone())),
-            name: format!("projects/{}/topics/{}", self.project(), name),
+    /// The base URL requests are sent to, e.g. `https://pubsub.googleapis.com`.
+    pub fn endpoint(&self) -> String {
+        self.0.read().unwrap().endpoint.clone()
+    }
+
+    /// The URL of a resource, optionally followed by a custom method like `:publish` or a
+    /// query string.
+    pub(crate) fn uri(
+        &self,
+        name: &dyn fmt::Display,
+        suffix: &str,
+    ) -> Result<hyper::Uri, error::Error> {
+        format!("{}/v1/{}{}", self.endpoint(), name, suffix)
+            .parse()
+            .map_err(|_| error::Error::InvalidResourceName(name.to_string()))
+    }
+
+    /// Fetches one page of a collection like `projects/my-project/topics`.
+    pub(crate) async fn list_page<T: DeserializeOwned>(
+        &self,
+        collection: &str,
+        page_size: Option<u32>,
+        page_token: Option<String>,
+    ) -> Result<Page<T>, error::Error> {
+        let mut query = Vec::new();
+        if let Some(page_size) = page_size {
+            query.push(("pageSize", page_size.to_string()));
+        }
+        if let Some(page_token) = page_token {
+            query.push(("pageToken", page_token));
         }
+        let query = serde_urlencoded::to_string(query)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+
+        let uri = self.uri(&collection, &format!("?{}", query))?;
+        let body = self
+            .perform(
+                hyper::Method::GET,
+                uri,
+                String::new(),
+                Operation::Admin,
+                &CallOptions::default(),
+            )
+            .await?;
+        let mut page: Page<T> = serde_json::from_slice(&body)?;
+        page.next_page_token = page.next_page_token.filter(|token| !token.is_empty());
+        Ok(page)
+    }
+
+    pub fn universe_domain(&self) -> String {
+        self.0.read().unwrap().universe_domain.clone()
+    }
+
+    /// A handle to a topic, by ID in the client's project or by full name.
+    pub fn topic<N: IntoResourceName<TopicName>>(&self, name: N) -> Result<Topic, error::Error> {
+        Ok(Topic {
+            client: Some(Client(self.0.clone())),
+            name: name.into_resource_name(self)?,
+            config: TopicConfig::default(),
+            options: CallOptions::default(),
+        })
     }
 
     pub fn is_running(&self) -> bool {
-        self.0.read().unwrap().running.load(Ordering::SeqCst)
+        !*self.0.read().unwrap().stopped.borrow()
     }
 
+    /// Signals background tasks, and loops checking `is_running`, to stop. Use `shutdown`
+    /// to also wait for them.
     pub fn stop(&self) {
-        self.0
-            .write()
-            .unwrap()
-            .running
-            .store(false, Ordering::SeqCst)
+        self.0.read().unwrap().stopped.send_replace(true);
+    }
+
+    /// Completes once `stop` or `shutdown` has been called.
+    pub async fn stopped(&self) {
+        let mut stopped = self.0.read().unwrap().stopped.subscribe();
+        let _ = stopped.wait_for(|stopped| *stopped).await;
     }
 
+    /// Stops the client and waits for operations in flight, such as pulls, to finish and
+    /// for background tasks to exit.
+    ///
+    /// Operations started while shutting down are waited for as well, and the client can
+    /// still be used afterwards, e.g. to delete a subscription.
+    pub async fn shutdown(&self) {
+        self.stop();
+        let mut in_flight = self.0.read().unwrap().in_flight.subscribe();
+        let _ = in_flight.wait_for(|n| *n == 0).await;
+
+        let tasks = mem::take(&mut self.0.write().unwrap().tasks);
+        for task in tasks {
+            if let Err(e) = task.await {
+                log::error!("Background task failed: {}", e);
+            }
+        }
+    }
+
+    /// Refreshes the token on a fixed interval until the client is stopped.
+    ///
+    /// Requests already refresh the token shortly before it expires, so this is only
+    /// needed to keep a token warm for idle clients.
     pub fn spawn_token_renew(&self, interval: Duration) {
-        let mut client = self.clone();
-        let c = self.clone();
+        let client = self.clone();
         let renew_token_task = async move {
             let mut int = time::interval(interval);
             loop {
-                if c.is_running() {
-                    int.tick().await;
-                    log::debug!("Renewing pubsub token");
-                    if let Err(e) = client.refresh_token().await {
-                        log::error!("Failed to update token: {}", e);
-                    }
+                tokio::select! {
+                    _ = int.tick() => {}
+                    _ = client.stopped() => break,
+                }
+                log::debug!("Renewing pubsub token");
+                if let Err(e) = client.refresh_token().await {
+                    log::error!("Failed to update token: {}", e);
                 }
             }
         };
 
-        task::spawn(renew_token_task);
+        let mut writable = self.0.write().unwrap();
+        writable.tasks.retain(|task| !task.is_finished());
+        writable.tasks.push(task::spawn(renew_token_task));
     }
 
-    pub async fn refresh_token(&mut self) -> Result<(), error::Error> {
-        match self.get_token().await {
-            Ok(token) => {
-                self.0.write().unwrap().token = Some(token);
-                Ok(())
-            }
-            Err(e) => Err(error::Error::from(e)),
+    /// Fetches a new token from the token provider, regardless of the current token's expiry.
+    pub async fn refresh_token(&self) -> Result<(), error::Error> {
+        let token = match self.get_token().await? {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        // An expiry too far away to represent is treated as never expiring.
+        let refresh_at = token.expires_in().and_then(|expires_in| {
+            Instant::now().checked_add(expires_in - TOKEN_REFRESH_MARGIN.min(expires_in / 2))
+        });
+        let mut writable = self.0.write().unwrap();
+        writable.token = Some(token);
+        writable.token_refresh_at = refresh_at;
+        Ok(())
+    }
+
+    async fn get_token(&self) -> Result<Option<Token>, error::Error> {
+        let token_provider = self.0.read().unwrap().token_provider.clone();
+        match token_provider {
+            Some(token_provider) => Ok(Some(token_provider.token(&self.hyper_client()).await?)),
+            None => Ok(None),
         }
     }
 
-    async fn get_token(&mut self) -> Result<goauth::auth::Token, goauth::GoErr> {
-        let credentials =
-            goauth::credentials::Credentials::from_str(&self.0.read().unwrap().credentials_string)
-                .unwrap();
+    /// Refreshes the token if it is missing or close to expiring.
+    ///
+    /// Concurrent callers wait on the same refresh rather than each fetching a token.
+    async fn ensure_token(&self) -> Result<(), error::Error> {
+        if self.0.read().unwrap().token_is_fresh() {
+            return Ok(());
+        }
 
-        self.set_project(credentials.project());
+        let refresh_lock = self.0.read().unwrap().refresh_lock.clone();
+        let _guard = refresh_lock.lock().await;
+        if self.0.read().unwrap().token_is_fresh() {
+            return Ok(());
+        }
 
-        let claims = JwtClaims::new(
-            credentials.iss(),
-            &Scope::PubSub,
-            credentials.token_uri(),
-            None,
-            None,
-        );
-        let jwt = Jwt::new(claims, credentials.rsa_key().unwrap(), None);
-        goauth::get_token(&jwt, &credentials).await
+        log::debug!("Refreshing pubsub token ahead of expiry");
+        self.refresh_token().await
     }
 
-    pub(crate) fn request<T: Into<hyper::Body>>(
+    pub(crate) async fn request<T: Into<hyper::Body>>(
         &self,
         method: hyper::Method,
         data: T,
-    ) -> hyper::Request<hyper::Body>
+    ) -> Result<hyper::Request<hyper::Body>, error::Error>
     where
         hyper::Body: std::convert::From<T>,
     {
+        self.ensure_token().await?;
+
         let mut req = hyper::Request::new(hyper::Body::from(data));
         *req.method_mut() = method;
         req.headers_mut().insert(
@@ -165,15 +389,145 @@ impl Client {
         );
         let readable = self.0.read().unwrap();
         req.headers_mut().insert(
-            hyper::header::AUTHORIZATION,
-            hyper::header::HeaderValue::from_str(&format!(
-                "{} {}",
-                readable.token_type(),
-                readable.access_token()
-            ))
-            .unwrap(),
+            hyper::header::USER_AGENT,
+            hyper::header::HeaderValue::from_str(&readable.user_agent).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+            })?,
         );
-        req
+        if let Some(authorization) = readable.authorization() {
+            req.headers_mut().insert(
+                hyper::header::AUTHORIZATION,
+                hyper::header::HeaderValue::from_str(&authorization).map_err(|_| {
+                    error::Error::InvalidCredentials(String::from(
+                        "Access token is not a valid header value",
+                    ))
+                })?,
+            );
+        }
+        Ok(req)
+    }
+
+    /// The default deadline for each operation.
+    pub fn timeout(&self) -> Duration {
+        self.0.read().unwrap().timeout
+    }
+
+    /// The default policy for retrying failed operations.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.0.read().unwrap().retry_policy.clone()
+    }
+
+    /// Sends a request and returns the body of the successful response, retrying failures
+    /// that are retryable for the operation.
+    ///
+    /// All attempts and the backoffs between them must finish within the operation's
+    /// timeout and the retry policy's total deadline, whichever is shorter, or the operation
+    /// fails with `Error::Timeout`. Error responses are returned as `Error::PubSub`.
+    pub(crate) async fn perform(
+        &self,
+        method: hyper::Method,
+        uri: hyper::Uri,
+        data: String,
+        operation: Operation,
+        options: &CallOptions,
+    ) -> Result<Bytes, error::Error> {
+        let _in_flight = InFlight::new(self.0.read().unwrap().in_flight.clone());
+        let policy = options
+            .retry_policy
+            .clone()
+            .unwrap_or_else(|| self.retry_policy());
+        let timeout = options.timeout.unwrap_or_else(|| self.timeout());
+        let timeout = policy
+            .total_deadline()
+            .map_or(timeout, |deadline| deadline.min(timeout));
+        // A deadline too far away to represent is treated as none.
+        let deadline = Instant::now().checked_add(timeout);
+
+        let mut attempt = 1;
+        loop {
+            let remaining =
+                deadline.map_or(timeout, |d| d.saturating_duration_since(Instant::now()));
+            let err = match time::timeout(
+                remaining,
+                self.perform_authenticated(method.clone(), uri.clone(), data.clone()),
+            )
+            .await
+            {
+                Ok(Ok(response)) if response.status().is_success() => {
+                    return Ok(response.into_body())
+                }
+                Ok(Ok(response)) => error::Error::PubSub(error::Status::from_response(
+                    response.status(),
+                    response.body(),
+                )),
+                Ok(Err(e)) => e,
+                Err(_) => return Err(error::Error::Timeout(timeout)),
+            };
+            if !operation.is_retryable(&err) || attempt >= policy.max_attempts() {
+                return Err(err);
+            }
+
+            let backoff = err.retry_after().unwrap_or_else(|| policy.backoff(attempt));
+            if deadline.is_some_and(|d| d.saturating_duration_since(Instant::now()) <= backoff) {
+                return Err(err);
+            }
+            log::debug!(
+                "{:?} attempt {} failed with {}, retrying in {:?}",
+                operation,
+                attempt,
+                err,
+                backoff
+            );
+            time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends a request, replaying it once with a fresh token if it is rejected as
+    /// unauthenticated.
+    async fn perform_authenticated(
+        &self,
+        method: hyper::Method,
+        uri: hyper::Uri,
+        data: String,
+    ) -> Result<hyper::Response<Bytes>, error::Error> {
+        let mut req = self.request(method.clone(), data.clone()).await?;
+        *req.uri_mut() = uri.clone();
+        let rejected = req.headers().get(hyper::header::AUTHORIZATION).cloned();
+
+        let response = self.send(req).await?;
+        if response.status() != hyper::StatusCode::UNAUTHORIZED || rejected.is_none() {
+            return Ok(response);
+        }
+
+        log::debug!("Request was unauthenticated, refreshing token and retrying");
+        self.refresh_rejected_token(rejected).await?;
+        let mut req = self.request(method, data).await?;
+        *req.uri_mut() = uri;
+        self.send(req).await
+    }
+
+    async fn send(
+        &self,
+        req: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<Bytes>, error::Error> {
+        let (parts, body) = self.hyper_client().request(req).await?.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        Ok(hyper::Response::from_parts(parts, body))
+    }
+
+    /// Forces a refresh unless another caller already replaced the rejected token.
+    async fn refresh_rejected_token(
+        &self,
+        rejected: Option<hyper::header::HeaderValue>,
+    ) -> Result<(), error::Error> {
+        let refresh_lock = self.0.read().unwrap().refresh_lock.clone();
+        let _guard = refresh_lock.lock().await;
+        let current = self.0.read().unwrap().authorization();
+        if rejected.is_some_and(|rejected| current.is_none_or(|current| rejected != current)) {
+            return Ok(());
+        }
+        self.refresh_token().await
     }
 
     pub fn hyper_client(&self) -> HyperClient {
@@ -181,7 +535,395 @@ impl Client {
     }
 }
 
-fn setup_hyper() -> HyperClient {
-    let https = HttpsConnector::new();
-    Arc::new(hyper::Client::builder().build::<_, hyper::Body>(https))
+/// Configures a `Client`.
+///
+/// Each client carries its own endpoint, credentials and connection pool, so a single
+/// process can talk to several emulators or to an emulator and production side by side.
+/// Without a token provider, requests are sent unauthenticated, as for the emulator.
+#[derive(Default)]
+pub struct ClientBuilder {
+    endpoint: Option<String>,
+    location: Option<String>,
+    universe_domain: Option<String>,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    project: Option<String>,
+    user_agent: Option<String>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    http2_only: bool,
+    timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    tls: TlsOptions,
+    proxy: Option<Proxy>,
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the base URL requests are sent to. Defaults to `https://pubsub.googleapis.com`,
+    /// or to `PUBSUB_EMULATOR_HOST` when it is set.
+    ///
+    /// An explicit endpoint takes precedence over `location` and `universe_domain`.
+    pub fn endpoint(mut self, endpoint: String) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
+    /// Sends requests through a locational endpoint, e.g. `us-east1` for
+    /// `https://us-east1-pubsub.googleapis.com`.
+    pub fn location(mut self, location: String) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// Targets a universe domain other than `googleapis.com`.
+    ///
+    /// The token provider must belong to the same universe domain.
+    pub fn universe_domain(mut self, universe_domain: String) -> Self {
+        self.universe_domain = Some(universe_domain);
+        self
+    }
+
+    /// Talks plain HTTP to the emulator at `host`, e.g. `localhost:8085`.
+    pub fn emulator(self, host: String) -> Self {
+        self.endpoint(format!("http://{}", host))
+    }
+
+    pub fn token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
+        self.token_provider = Some(token_provider);
+        self
+    }
+
+    /// Sets the project, overriding the one known to the token provider.
+    pub fn project(mut self, project: String) -> Self {
+        self.project = Some(project);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: String) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max_idle);
+        self
+    }
+
+    pub fn http2_only(mut self, http2_only: bool) -> Self {
+        self.http2_only = http2_only;
+        self
+    }
+
+    /// Sets the default deadline for each operation, covering token refreshes, the requests
+    /// and reading the responses of all attempts, and the backoffs between them. Defaults to
+    /// 60 seconds.
+    ///
+    /// Individual `Topic` and `Subscription` handles can override it with `with_timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the default policy for retrying failed operations. Defaults to
+    /// `RetryPolicy::default()`.
+    ///
+    /// Individual `Topic` and `Subscription` handles can override it with
+    /// `with_retry_policy`.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Trusts the PEM encoded CA certificates in addition to the built-in roots, e.g. for
+    /// a TLS-intercepting proxy.
+    pub fn add_root_certificate(mut self, pem: Vec<u8>) -> Self {
+        self.tls.root_certificates.push(pem);
+        self
+    }
+
+    /// Presents a client certificate for mutual TLS. Both arguments are PEM encoded and the
+    /// key must be in PKCS#8 format.
+    pub fn identity(mut self, cert_chain_pem: Vec<u8>, private_key_pem: Vec<u8>) -> Self {
+        self.tls.identity = Some((cert_chain_pem, private_key_pem));
+        self
+    }
+
+    /// Tunnels HTTPS requests, including token exchanges, through `proxy`.
+    ///
+    /// Defaults to `Proxy::from_env`.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Builds the client and fetches its first token.
+    ///
+    /// When no project was set, it is taken from the token provider and falls back to
+    /// `GOOGLE_CLOUD_PROJECT`.
+    pub async fn build(self) -> Result<Client, error::Error> {
+        let token_provider = self.token_provider.clone();
+        let mut client = self.build_client()?;
+
+        if let Some(token_provider) = token_provider {
+            let universe_domain = token_provider
+                .universe_domain(&client.hyper_client())
+                .await?;
+            if universe_domain != client.universe_domain() {
+                return Err(error::Error::InvalidCredentials(format!(
+                    "Credentials belong to universe domain {} but the client targets {}",
+                    universe_domain,
+                    client.universe_domain()
+                )));
+            }
+            if client.0.read().unwrap().project.is_none() {
+                if let Some(project) = token_provider.project(&client.hyper_client()).await? {
+                    client.set_project(project);
+                }
+            }
+            client.refresh_token().await?;
+        }
+
+        if client.0.read().unwrap().project.is_none() {
+            if let Some(project) = project_from_env() {
+                client.set_project(project);
+            }
+        }
+
+        Ok(client)
+    }
+
+    /// The endpoint to use, given the value of `PUBSUB_EMULATOR_HOST`.
+    fn resolve_endpoint(&self, emulator_host: Option<String>) -> String {
+        if let Some(endpoint) = &self.endpoint {
+            return endpoint.clone();
+        }
+        if self.location.is_none() && self.universe_domain.is_none() {
+            return emulator_host
+                .map(|host| format!("http://{}", host))
+                .unwrap_or_else(|| String::from(DEFAULT_ENDPOINT));
+        }
+
+        let universe_domain = self
+            .universe_domain
+            .as_deref()
+            .unwrap_or(DEFAULT_UNIVERSE_DOMAIN);
+        match &self.location {
+            Some(location) => format!("https://{}-pubsub.{}", location, universe_domain),
+            None => format!("https://pubsub.{}", universe_domain),
+        }
+    }
+
+    fn build_client(self) -> Result<Client, error::Error> {
+        let hyper_client = setup_hyper(&self)?;
+        let endpoint = self.resolve_endpoint(env::var("PUBSUB_EMULATOR_HOST").ok());
+        Ok(Client(Arc::new(RwLock::new(State {
+            token: None,
+            token_refresh_at: None,
+            token_provider: self.token_provider,
+            refresh_lock: Arc::new(Mutex::new(())),
+            endpoint,
+            universe_domain: self
+                .universe_domain
+                .unwrap_or_else(auth::default_universe_domain),
+            user_agent: self
+                .user_agent
+                .unwrap_or_else(|| String::from(DEFAULT_USER_AGENT)),
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            retry_policy: self.retry_policy.clone().unwrap_or_default(),
+            project: self.project,
+            hyper_client,
+            stopped: Arc::new(watch::channel(false).0),
+            in_flight: Arc::new(watch::channel(0).0),
+            tasks: Vec::new(),
+        }))))
+    }
+}
+
+/// An unauthenticated client for the emulator at `host`, leaving the project unset when
+/// none is given.
+fn emulator_client(host: String, project: Option<String>) -> Result<Client, error::Error> {
+    let mut builder = ClientBuilder::new().emulator(host);
+    if let Some(project) = project {
+        builder = builder.project(project);
+    }
+    builder.build_client()
+}
+
+/// `GOOGLE_CLOUD_PROJECT`, unless it is unset or empty.
+fn project_from_env() -> Option<String> {
+    env::var("GOOGLE_CLOUD_PROJECT")
+        .ok()
+        .filter(|project| !project.is_empty())
+}
+
+fn setup_hyper(options: &ClientBuilder) -> Result<HyperClient, error::Error> {
+    let proxy = options.proxy.clone().or_else(Proxy::from_env);
+    let https = connector::https_connector(&options.tls, proxy)?;
+    let mut builder = hyper::Client::builder();
+    if let Some(timeout) = options.pool_idle_timeout {
+        builder.pool_idle_timeout(timeout);
+    }
+    if let Some(max_idle) = options.pool_max_idle_per_host {
+        builder.pool_max_idle_per_host(max_idle);
+    }
+    builder.http2_only(options.http2_only);
+    Ok(Arc::new(builder.build::<_, hyper::Body>(https)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::StaticToken;
+    use async_trait::async_trait;
+
+    fn static_token() -> Arc<StaticToken> {
+        Arc::new(StaticToken::new(Token::new(
+            "token".to_string(),
+            "Bearer".to_string(),
+            None,
+        )))
+    }
+
+    /// Credentials of another universe domain.
+    struct UniverseToken(&'static str);
+
+    #[async_trait]
+    impl TokenProvider for UniverseToken {
+        async fn token(&self, _client: &HyperClient) -> Result<Token, error::Error> {
+            Ok(Token::new("token".to_string(), "Bearer".to_string(), None))
+        }
+
+        async fn universe_domain(&self, _client: &HyperClient) -> Result<String, error::Error> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_token_without_representable_expiry_never_refreshes() {
+        let token = Token::new(
+            "token".to_string(),
+            "Bearer".to_string(),
+            Some(Duration::MAX),
+        );
+        let client = ClientBuilder::new()
+            .token_provider(Arc::new(StaticToken::new(token)))
+            .build_client()
+            .unwrap();
+
+        client.refresh_token().await.unwrap();
+        let state = client.0.read().unwrap();
+        assert!(state.token_refresh_at.is_none());
+        assert!(state.token_is_fresh());
+    }
+
+    #[test]
+    fn emulator_client_without_project_leaves_it_unset() {
+        let client = emulator_client("localhost:8085".to_string(), None).unwrap();
+        assert_eq!(client.endpoint(), "http://localhost:8085");
+        assert!(matches!(
+            client.project(),
+            Err(error::Error::MissingProject)
+        ));
+        assert!(matches!(
+            client.topic("abc"),
+            Err(error::Error::MissingProject)
+        ));
+
+        let client = emulator_client("localhost:8085".to_string(), Some("p".to_string())).unwrap();
+        assert_eq!(
+            client.topic("abc").unwrap().name.to_string(),
+            "projects/p/topics/abc"
+        );
+    }
+
+    #[test]
+    fn endpoint_defaults_to_production_or_the_emulator() {
+        let builder = ClientBuilder::new();
+        assert_eq!(
+            builder.resolve_endpoint(None),
+            "https://pubsub.googleapis.com"
+        );
+        assert_eq!(
+            builder.resolve_endpoint(Some("localhost:8085".to_string())),
+            "http://localhost:8085"
+        );
+    }
+
+    #[test]
+    fn endpoint_follows_location_and_universe_domain() {
+        let emulator_host = || Some("localhost:8085".to_string());
+        let location = ClientBuilder::new().location("us-east1".to_string());
+        assert_eq!(
+            location.resolve_endpoint(emulator_host()),
+            "https://us-east1-pubsub.googleapis.com"
+        );
+
+        let universe = ClientBuilder::new().universe_domain("example.goog".to_string());
+        assert_eq!(
+            universe.resolve_endpoint(emulator_host()),
+            "https://pubsub.example.goog"
+        );
+
+        let both = ClientBuilder::new()
+            .location("europe-west1".to_string())
+            .universe_domain("example.goog".to_string());
+        assert_eq!(
+            both.resolve_endpoint(emulator_host()),
+            "https://europe-west1-pubsub.example.goog"
+        );
+    }
+
+    #[test]
+    fn explicit_endpoint_takes_precedence() {
+        let builder = ClientBuilder::new()
+            .endpoint("https://pubsub.internal:8443".to_string())
+            .location("us-east1".to_string())
+            .universe_domain("example.goog".to_string());
+        assert_eq!(
+            builder.resolve_endpoint(Some("localhost:8085".to_string())),
+            "https://pubsub.internal:8443"
+        );
+        assert_eq!(
+            ClientBuilder::new()
+                .emulator("localhost:9000".to_string())
+                .resolve_endpoint(Some("localhost:8085".to_string())),
+            "http://localhost:9000"
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_must_match_the_universe_domain() {
+        let result = ClientBuilder::new()
+            .universe_domain("example.goog".to_string())
+            .project("p".to_string())
+            .token_provider(static_token())
+            .build()
+            .await;
+        assert!(matches!(result, Err(error::Error::InvalidCredentials(_))));
+
+        let result = ClientBuilder::new()
+            .project("p".to_string())
+            .token_provider(Arc::new(UniverseToken("example.goog")))
+            .build()
+            .await;
+        assert!(matches!(result, Err(error::Error::InvalidCredentials(_))));
+
+        let client = ClientBuilder::new()
+            .universe_domain("example.goog".to_string())
+            .project("p".to_string())
+            .token_provider(Arc::new(UniverseToken("example.goog")))
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(client.universe_domain(), "example.goog");
+        assert_eq!(client.endpoint(), "https://pubsub.example.goog");
+    }
 }
@@ -0,0 +1,137 @@
+use crate::error;
+use crate::proxy::{Proxy, ProxyConnector};
+use hyper::client::HttpConnector;
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("Either the `native-tls` or the `rustls` feature must be enabled");
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub type HttpsConnector = hyper_tls::HttpsConnector<ProxyConnector>;
+
+#[cfg(feature = "rustls")]
+pub type HttpsConnector = hyper_rustls::HttpsConnector<ProxyConnector>;
+
+/// Extra TLS configuration on top of the platform's trust store.
+#[derive(Default, Clone)]
+pub(crate) struct TlsOptions {
+    /// PEM encoded CA certificates to trust in addition to the built-in roots.
+    pub(crate) root_certificates: Vec<Vec<u8>>,
+    /// PEM encoded client certificate chain and private key for mutual TLS.
+    pub(crate) identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+fn proxy_connector(proxy: Option<Proxy>) -> ProxyConnector {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    ProxyConnector::new(http, proxy)
+}
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub(crate) fn https_connector(
+    options: &TlsOptions,
+    proxy: Option<Proxy>,
+) -> Result<HttpsConnector, error::Error> {
+    let mut tls = native_tls::TlsConnector::builder();
+    for pem in &options.root_certificates {
+        for certificate in pem_certificates(pem)? {
+            tls.add_root_certificate(
+                native_tls::Certificate::from_pem(certificate.as_bytes()).map_err(tls_error)?,
+            );
+        }
+    }
+    if let Some((cert_chain, private_key)) = &options.identity {
+        tls.identity(native_tls::Identity::from_pkcs8(cert_chain, private_key).map_err(tls_error)?);
+    }
+    let tls = tls.build().map_err(tls_error)?;
+    Ok(HttpsConnector::from((
+        proxy_connector(proxy),
+        tokio_native_tls::TlsConnector::from(tls),
+    )))
+}
+
+/// Splits a PEM bundle into its certificates, since `native_tls::Certificate::from_pem`
+/// only reads the first one.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+fn pem_certificates(pem: &[u8]) -> Result<Vec<&str>, error::Error> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let mut pem = std::str::from_utf8(pem).map_err(tls_error)?;
+    let mut certificates = Vec::new();
+    while let Some(start) = pem.find(BEGIN) {
+        let end = pem[start..]
+            .find(END)
+            .ok_or_else(|| tls_error("Unterminated certificate in PEM"))?
+            + start
+            + END.len();
+        certificates.push(&pem[start..end]);
+        pem = &pem[end..];
+    }
+    if certificates.is_empty() {
+        return Err(tls_error("No certificate found in PEM"));
+    }
+    Ok(certificates)
+}
+
+#[cfg(feature = "rustls")]
+pub(crate) fn https_connector(
+    options: &TlsOptions,
+    proxy: Option<Proxy>,
+) -> Result<HttpsConnector, error::Error> {
+    use std::io::BufReader;
+
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs()? {
+        if let Err(e) = roots.add(&rustls::Certificate(cert.0)) {
+            log::debug!("Skipping invalid native root certificate: {}", e);
+        }
+    }
+    for pem in &options.root_certificates {
+        for der in rustls_pemfile::certs(&mut BufReader::new(pem.as_slice()))? {
+            roots.add(&rustls::Certificate(der)).map_err(tls_error)?;
+        }
+    }
+
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    let config = match &options.identity {
+        Some((cert_chain, private_key)) => {
+            let cert_chain = rustls_pemfile::certs(&mut BufReader::new(cert_chain.as_slice()))?
+                .into_iter()
+                .map(rustls::Certificate)
+                .collect();
+            config
+                .with_client_auth_cert(cert_chain, private_key_from_pem(private_key)?)
+                .map_err(tls_error)?
+        }
+        None => config.with_no_client_auth(),
+    };
+
+    Ok(hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(config)
+        .https_or_http()
+        .enable_http1()
+        .enable_http2()
+        .wrap_connector(proxy_connector(proxy)))
+}
+
+#[cfg(feature = "rustls")]
+fn private_key_from_pem(pem: &[u8]) -> Result<rustls::PrivateKey, error::Error> {
+    use rustls_pemfile::Item;
+
+    let mut reader = std::io::BufReader::new(pem);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => {
+                return Ok(rustls::PrivateKey(key))
+            }
+            _ => continue,
+        }
+    }
+    Err(tls_error("No private key found in PEM"))
+}
+
+fn tls_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> error::Error {
+    error::Error::Tls(err.into())
+}
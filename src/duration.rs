@@ -0,0 +1,48 @@
+//! Serde helpers for `google.protobuf.Duration` in its JSON form, e.g. `"1.5s"`.
+
+use serde::{Deserialize, Deserializer, Serializer};
+use std::time::Duration;
+
+pub(crate) fn serialize<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let formatted = if duration.subsec_nanos() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        let nanos = format!("{:09}", duration.subsec_nanos());
+        format!("{}.{}s", duration.as_secs(), nanos.trim_end_matches('0'))
+    };
+    serializer.serialize_str(&formatted)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.strip_suffix('s')
+        .and_then(|secs| secs.parse::<f64>().ok())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid duration {:?}", s)))
+}
+
+pub(crate) mod option {
+    use serde::{Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        super::deserialize(deserializer).map(Some)
+    }
+}
@@ -0,0 +1,225 @@
+use crate::client::Client;
+use crate::error;
+use crate::name::{IntoResourceName, SubscriptionName, TopicName};
+use crate::subscription::{Subscription, SubscriptionConfig};
+use crate::topic::{Topic, TopicConfig};
+use serde::Serialize;
+use serde_json::Value;
+
+/// The result of `Client::ensure_topic` or `Client::ensure_subscription`.
+pub struct Ensured<T> {
+    pub resource: T,
+    /// Whether this call created the resource.
+    pub created: bool,
+    /// The fields of an existing resource that differ from the desired configuration.
+    /// They are not changed; pass the field names to `update` to apply them.
+    pub drift: Vec<Drift>,
+}
+
+/// A field whose actual value differs from the desired one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    /// The field name as in the REST API, e.g. `ackDeadlineSeconds`.
+    pub field: String,
+    pub desired: Value,
+    /// The current value, `Value::Null` when the field is not set.
+    pub actual: Value,
+}
+
+impl Client {
+    /// Creates the topic unless it already exists, in which case the existing topic is
+    /// returned along with how its configuration differs from `config`.
+    ///
+    /// Creation is attempted first, so concurrent callers do not race.
+    pub async fn ensure_topic<N: IntoResourceName<TopicName>>(
+        &self,
+        name: N,
+        config: TopicConfig,
+    ) -> Result<Ensured<Topic>, error::Error> {
+        let name = name.into_resource_name(self)?;
+        match self.create_topic(name.clone(), config.clone()).await {
+            Ok(topic) => Ok(Ensured {
+                resource: topic,
+                created: true,
+                drift: Vec::new(),
+            }),
+            Err(e) if e.is_already_exists() => {
+                let topic = self.topic(name)?.get().await?;
+                let drift = drift(&config, &topic.config)?;
+                Ok(Ensured {
+                    resource: topic,
+                    created: false,
+                    drift,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Creates the subscription unless it already exists, in which case the existing
+    /// subscription is returned along with how its topic and configuration differ from
+    /// the desired ones.
+    ///
+    /// Creation is attempted first, so concurrent callers do not race.
+    pub async fn ensure_subscription<N: IntoResourceName<SubscriptionName>>(
+        &self,
+        name: N,
+        topic: &Topic,
+        config: SubscriptionConfig,
+    ) -> Result<Ensured<Subscription>, error::Error> {
+        let name = name.into_resource_name(self)?;
+        match self
+            .create_subscription(name.clone(), topic, config.clone())
+            .await
+        {
+            Ok(subscription) => Ok(Ensured {
+                resource: subscription,
+                created: true,
+                drift: Vec::new(),
+            }),
+            Err(e) if e.is_already_exists() => {
+                let subscription = self.subscribe(name)?.get().await?;
+                let mut desired = subscription.clone();
+                desired.topic = Some(topic.name.clone());
+                desired.config = config;
+                let drift = drift(&desired, &subscription)?;
+                Ok(Ensured {
+                    resource: subscription,
+                    created: false,
+                    drift,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Compares the fields set in `desired` with `actual`. Fields left unset in `desired`,
+/// including those of nested objects, are not compared, since the service fills in
+/// defaults for them. Drift is reported per top-level field, as used in update masks.
+///
+/// The service leaves default values like `false`, `0` and `""` out of its responses, so
+/// a missing field in `actual` matches a default value in `desired`.
+pub(crate) fn drift<T: Serialize>(desired: &T, actual: &T) -> Result<Vec<Drift>, error::Error> {
+    let (desired, actual) = match (
+        serde_json::to_value(desired)?,
+        serde_json::to_value(actual)?,
+    ) {
+        (Value::Object(desired), Value::Object(actual)) => (desired, actual),
+        _ => return Ok(Vec::new()),
+    };
+    Ok(desired
+        .into_iter()
+        .filter_map(|(field, desired)| {
+            let actual = actual.get(&field).cloned().unwrap_or(Value::Null);
+            (!is_satisfied(&desired, &actual)).then_some(Drift {
+                field,
+                desired,
+                actual,
+            })
+        })
+        .collect())
+}
+
+/// Whether `actual` matches `desired` in every field `desired` sets.
+fn is_satisfied(desired: &Value, actual: &Value) -> bool {
+    match (desired, actual) {
+        (Value::Object(desired), Value::Object(actual)) => {
+            desired.iter().all(|(field, desired)| {
+                is_satisfied(desired, actual.get(field).unwrap_or(&Value::Null))
+            })
+        }
+        (Value::Object(desired), Value::Null) => desired
+            .values()
+            .all(|desired| is_satisfied(desired, &Value::Null)),
+        (desired, Value::Null) => is_default(desired),
+        _ => desired == actual,
+    }
+}
+
+/// Whether `value` is the default of its type, which the service omits.
+fn is_default(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(value) => !value,
+        Value::Number(value) => value.as_f64() == Some(0.0),
+        Value::String(value) => value.is_empty(),
+        Value::Array(values) => values.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(desired: Value, actual: Value) -> Vec<String> {
+        drift(&desired, &actual)
+            .unwrap()
+            .into_iter()
+            .map(|drift| drift.field)
+            .collect()
+    }
+
+    #[test]
+    fn ignores_defaults_filled_in_by_the_service() {
+        let desired = json!({
+            "ackDeadlineSeconds": 20,
+            "deadLetterPolicy": {"deadLetterTopic": "projects/p/topics/dead"},
+            "retryPolicy": {"minimumBackoff": "10s"},
+        });
+        let actual = json!({
+            "ackDeadlineSeconds": 20,
+            "deadLetterPolicy": {"deadLetterTopic": "projects/p/topics/dead", "maxDeliveryAttempts": 5},
+            "retryPolicy": {"minimumBackoff": "10s", "maximumBackoff": "600s"},
+            "messageRetentionDuration": "604800s",
+        });
+        assert!(fields(desired, actual).is_empty());
+    }
+
+    #[test]
+    fn reports_nested_changes_by_top_level_field() {
+        let desired = json!({
+            "ackDeadlineSeconds": 20,
+            "deadLetterPolicy": {"deadLetterTopic": "projects/p/topics/dead", "maxDeliveryAttempts": 10},
+            "retryPolicy": {"minimumBackoff": "10s"},
+        });
+        let actual = json!({
+            "ackDeadlineSeconds": 10,
+            "deadLetterPolicy": {"deadLetterTopic": "projects/p/topics/dead", "maxDeliveryAttempts": 5},
+        });
+        assert_eq!(
+            fields(desired, actual),
+            ["ackDeadlineSeconds", "deadLetterPolicy", "retryPolicy"]
+        );
+    }
+
+    #[test]
+    fn missing_fields_match_default_values() {
+        let desired = json!({
+            "retainAckedMessages": false,
+            "enableMessageOrdering": false,
+            "filter": "",
+            "labels": {},
+            "deadLetterPolicy": {"maxDeliveryAttempts": 0},
+        });
+        assert!(fields(desired, json!({})).is_empty());
+
+        let desired = json!({
+            "retainAckedMessages": true,
+            "ackDeadlineSeconds": 10,
+            "filter": "attributes.kind = \"paid\"",
+            "deadLetterPolicy": {"maxDeliveryAttempts": 5},
+        });
+        assert_eq!(
+            fields(desired, json!({})),
+            [
+                "ackDeadlineSeconds",
+                "deadLetterPolicy",
+                "filter",
+                "retainAckedMessages"
+            ]
+        );
+    }
+}
@@ -1,6 +1,9 @@
+use hyper::StatusCode;
 use serde_derive::Deserialize;
+use std::collections::HashMap;
 use std::fmt;
 use std::io;
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
@@ -15,11 +18,313 @@ pub enum Error {
     Base64(base64::DecodeError),
     #[serde(skip_deserializing)]
     IO(io::Error),
-    PubSub {
-        code: i32,
-        message: String,
-        status: String,
-    },
+    #[serde(skip_deserializing)]
+    Tls(Box<dyn std::error::Error + Send + Sync>),
+    #[serde(skip_deserializing)]
+    Timeout(Duration),
+    #[serde(skip_deserializing)]
+    MissingClient,
+    #[serde(skip_deserializing)]
+    MissingProject,
+    #[serde(skip_deserializing)]
+    InvalidCredentials(String),
+    #[serde(skip_deserializing)]
+    InvalidResourceName(String),
+    #[serde(skip_deserializing)]
+    InvalidManifest(String),
+    PubSub(Status),
+}
+
+impl Error {
+    /// The status returned by Pub/Sub, if the error came from the service.
+    pub fn status(&self) -> Option<&Status> {
+        match self {
+            Error::PubSub(status) => Some(status),
+            _ => None,
+        }
+    }
+
+    /// Whether the operation may succeed if it is tried again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::PubSub(status) => status.is_retryable(),
+            Error::Http(e) => is_transport_error(e),
+            Error::Timeout(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status().is_some_and(Status::is_not_found)
+    }
+
+    pub fn is_already_exists(&self) -> bool {
+        self.status().is_some_and(Status::is_already_exists)
+    }
+
+    /// How long the service asked to wait before retrying.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.status().and_then(Status::retry_after)
+    }
+}
+
+/// Whether a hyper error is a connection failure rather than a malformed request or
+/// response.
+pub(crate) fn is_transport_error(err: &hyper::Error) -> bool {
+    !err.is_user() && !err.is_parse()
+}
+
+/// A `google.rpc.Status` error returned by Pub/Sub.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "RawStatus")]
+pub struct Status {
+    pub code: Code,
+    /// The HTTP status of the response.
+    pub http_status: u16,
+    pub message: String,
+    pub details: Vec<Detail>,
+}
+
+impl Status {
+    /// Parses the error body of a failed response, falling back to the HTTP status when
+    /// it is not a `google.rpc.Status`.
+    pub(crate) fn from_response(status: StatusCode, body: &[u8]) -> Self {
+        #[derive(Deserialize)]
+        struct ErrorBody {
+            error: Status,
+        }
+
+        match serde_json::from_slice::<ErrorBody>(body) {
+            Ok(body) => body.error,
+            Err(_) => Status {
+                code: Code::from_http(status),
+                http_status: status.as_u16(),
+                message: String::from_utf8_lossy(body).into_owned(),
+                details: Vec::new(),
+            },
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.code.is_retryable()
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.code == Code::NotFound
+    }
+
+    pub fn is_already_exists(&self) -> bool {
+        self.code == Code::AlreadyExists
+    }
+
+    /// The delay requested by a `RetryInfo` detail.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.details.iter().find_map(|detail| match detail {
+            Detail::RetryInfo(info) => Some(info.retry_delay),
+            _ => None,
+        })
+    }
+
+    pub fn error_info(&self) -> Option<&ErrorInfo> {
+        self.details.iter().find_map(|detail| match detail {
+            Detail::ErrorInfo(info) => Some(info),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawStatus {
+    #[serde(default)]
+    code: u16,
+    #[serde(default)]
+    message: String,
+    status: Option<Code>,
+    #[serde(default)]
+    details: Vec<serde_json::Value>,
+}
+
+impl From<RawStatus> for Status {
+    fn from(raw: RawStatus) -> Self {
+        let code = raw.status.unwrap_or_else(|| {
+            StatusCode::from_u16(raw.code).map_or(Code::Unknown, Code::from_http)
+        });
+        Status {
+            code,
+            http_status: raw.code,
+            message: raw.message,
+            details: raw.details.into_iter().map(Detail::from).collect(),
+        }
+    }
+}
+
+/// The canonical error codes of Google APIs.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Code {
+    Ok,
+    Cancelled,
+    InvalidArgument,
+    DeadlineExceeded,
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    ResourceExhausted,
+    FailedPrecondition,
+    Aborted,
+    OutOfRange,
+    Unimplemented,
+    Internal,
+    Unavailable,
+    DataLoss,
+    Unauthenticated,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Code {
+    /// The code Google maps to an HTTP status, for responses without a `status`.
+    pub fn from_http(status: StatusCode) -> Self {
+        match status.as_u16() {
+            200..=299 => Code::Ok,
+            400 => Code::InvalidArgument,
+            401 => Code::Unauthenticated,
+            403 => Code::PermissionDenied,
+            404 => Code::NotFound,
+            409 => Code::Aborted,
+            429 => Code::ResourceExhausted,
+            499 => Code::Cancelled,
+            501 => Code::Unimplemented,
+            502 | 503 => Code::Unavailable,
+            504 => Code::DeadlineExceeded,
+            500..=599 => Code::Internal,
+            _ => Code::Unknown,
+        }
+    }
+
+    /// Whether the code signals a transient condition.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            Code::Unavailable
+                | Code::DeadlineExceeded
+                | Code::ResourceExhausted
+                | Code::Aborted
+                | Code::Internal
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::Ok => "OK",
+            Code::Cancelled => "CANCELLED",
+            Code::Unknown => "UNKNOWN",
+            Code::InvalidArgument => "INVALID_ARGUMENT",
+            Code::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            Code::NotFound => "NOT_FOUND",
+            Code::AlreadyExists => "ALREADY_EXISTS",
+            Code::PermissionDenied => "PERMISSION_DENIED",
+            Code::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Code::FailedPrecondition => "FAILED_PRECONDITION",
+            Code::Aborted => "ABORTED",
+            Code::OutOfRange => "OUT_OF_RANGE",
+            Code::Unimplemented => "UNIMPLEMENTED",
+            Code::Internal => "INTERNAL",
+            Code::Unavailable => "UNAVAILABLE",
+            Code::DataLoss => "DATA_LOSS",
+            Code::Unauthenticated => "UNAUTHENTICATED",
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A decoded entry of `google.rpc.Status.details`.
+#[derive(Debug, Clone)]
+pub enum Detail {
+    ErrorInfo(ErrorInfo),
+    RetryInfo(RetryInfo),
+    QuotaFailure(QuotaFailure),
+    BadRequest(BadRequest),
+    /// A detail of another type, or one that could not be decoded.
+    Other(serde_json::Value),
+}
+
+impl From<serde_json::Value> for Detail {
+    fn from(value: serde_json::Value) -> Self {
+        let decoded = match value.get("@type").and_then(|t| t.as_str()) {
+            Some("type.googleapis.com/google.rpc.ErrorInfo") => {
+                serde_json::from_value(value.clone()).map(Detail::ErrorInfo)
+            }
+            Some("type.googleapis.com/google.rpc.RetryInfo") => {
+                serde_json::from_value(value.clone()).map(Detail::RetryInfo)
+            }
+            Some("type.googleapis.com/google.rpc.QuotaFailure") => {
+                serde_json::from_value(value.clone()).map(Detail::QuotaFailure)
+            }
+            Some("type.googleapis.com/google.rpc.BadRequest") => {
+                serde_json::from_value(value.clone()).map(Detail::BadRequest)
+            }
+            _ => return Detail::Other(value),
+        };
+        decoded.unwrap_or(Detail::Other(value))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ErrorInfo {
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryInfo {
+    #[serde(with = "crate::duration")]
+    pub retry_delay: Duration,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QuotaFailure {
+    #[serde(default)]
+    pub violations: Vec<QuotaViolation>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QuotaViolation {
+    #[serde(default)]
+    pub subject: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BadRequest {
+    #[serde(default)]
+    pub field_violations: Vec<FieldViolation>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FieldViolation {
+    #[serde(default)]
+    pub field: String,
+    #[serde(default)]
+    pub description: String,
 }
 
 impl fmt::Display for Error {
@@ -30,15 +335,20 @@ impl fmt::Display for Error {
             Error::Json(e) => write!(f, "Json({})", e),
             Error::Base64(e) => write!(f, "Base64({})", e),
             Error::IO(e) => write!(f, "IO({})", e),
-            Error::PubSub {
-                code,
-                message,
-                status,
-            } => write!(
+            Error::Tls(e) => write!(f, "Tls({})", e),
+            Error::Timeout(d) => write!(f, "Timeout({:?})", d),
+            Error::MissingClient => write!(
                 f,
-                "PubSub(code: {}, status: {}, message: {})",
-                code, status, message
+                "MissingClient(topics and subscriptions must be created using a client)"
             ),
+            Error::MissingProject => write!(
+                f,
+                "MissingProject(set it with ClientBuilder::project or Client::set_project)"
+            ),
+            Error::InvalidCredentials(e) => write!(f, "InvalidCredentials({})", e),
+            Error::InvalidResourceName(name) => write!(f, "InvalidResourceName({})", name),
+            Error::InvalidManifest(e) => write!(f, "InvalidManifest({})", e),
+            Error::PubSub(status) => write!(f, "PubSub({})", status),
         }
     }
 }
@@ -74,3 +384,137 @@ impl From<io::Error> for Error {
         Error::IO(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(http_status: u16, body: &str) -> Status {
+        Status::from_response(StatusCode::from_u16(http_status).unwrap(), body.as_bytes())
+    }
+
+    #[test]
+    fn parses_a_full_status() {
+        let status = status(
+            429,
+            r#"{"error": {
+                "code": 429,
+                "message": "Quota exceeded",
+                "status": "RESOURCE_EXHAUSTED",
+                "details": [
+                    {"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "1.25s"},
+                    {
+                        "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+                        "reason": "RATE_LIMIT_EXCEEDED",
+                        "domain": "pubsub.googleapis.com",
+                        "metadata": {"quota_limit": "publish"}
+                    },
+                    {"@type": "type.googleapis.com/google.rpc.Help", "links": []}
+                ]
+            }}"#,
+        );
+        assert_eq!(status.code, Code::ResourceExhausted);
+        assert_eq!(status.http_status, 429);
+        assert_eq!(status.message, "Quota exceeded");
+        assert_eq!(status.retry_after(), Some(Duration::from_millis(1250)));
+
+        let info = status.error_info().unwrap();
+        assert_eq!(info.reason, "RATE_LIMIT_EXCEEDED");
+        assert_eq!(info.domain, "pubsub.googleapis.com");
+        assert_eq!(info.metadata["quota_limit"], "publish");
+
+        assert_eq!(status.details.len(), 3);
+        assert!(matches!(&status.details[2], Detail::Other(value)
+            if value["@type"] == "type.googleapis.com/google.rpc.Help"));
+        assert_eq!(status.to_string(), "RESOURCE_EXHAUSTED: Quota exceeded");
+    }
+
+    #[test]
+    fn parses_fractional_retry_delays() {
+        let retry_after = |delay: &str| {
+            let body = serde_json::json!({"error": {"code": 503, "details": [{
+                "@type": "type.googleapis.com/google.rpc.RetryInfo",
+                "retryDelay": delay,
+            }]}});
+            status(503, &body.to_string()).retry_after()
+        };
+        assert_eq!(retry_after("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(retry_after("0.5s"), Some(Duration::from_millis(500)));
+        assert_eq!(retry_after("0.001s"), Some(Duration::from_millis(1)));
+        assert_eq!(retry_after("1.000000001s"), Some(Duration::new(1, 1)));
+        // A malformed delay keeps the detail, undecoded.
+        assert_eq!(retry_after("soon"), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_http_status() {
+        let status = self::status(503, "upstream connect error");
+        assert_eq!(status.code, Code::Unavailable);
+        assert_eq!(status.http_status, 503);
+        assert_eq!(status.message, "upstream connect error");
+        assert!(status.details.is_empty());
+
+        assert_eq!(self::status(404, "").code, Code::NotFound);
+        assert_eq!(self::status(502, "<html></html>").code, Code::Unavailable);
+        assert_eq!(
+            self::status(409, r#"{"error": {"code": 409}}"#).code,
+            Code::Aborted
+        );
+        assert_eq!(
+            self::status(400, r#"{"error": {"code": 400, "status": "NEW_CODE"}}"#).code,
+            Code::Unknown
+        );
+    }
+
+    #[test]
+    fn maps_http_statuses_to_codes() {
+        let code = |http_status| Code::from_http(StatusCode::from_u16(http_status).unwrap());
+        assert_eq!(code(200), Code::Ok);
+        assert_eq!(code(400), Code::InvalidArgument);
+        assert_eq!(code(401), Code::Unauthenticated);
+        assert_eq!(code(403), Code::PermissionDenied);
+        assert_eq!(code(404), Code::NotFound);
+        assert_eq!(code(409), Code::Aborted);
+        assert_eq!(code(429), Code::ResourceExhausted);
+        assert_eq!(code(499), Code::Cancelled);
+        assert_eq!(code(500), Code::Internal);
+        assert_eq!(code(501), Code::Unimplemented);
+        assert_eq!(code(503), Code::Unavailable);
+        assert_eq!(code(504), Code::DeadlineExceeded);
+        assert_eq!(code(418), Code::Unknown);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let error = |code: &str| {
+            let body = format!(r#"{{"error": {{"code": 400, "status": "{}"}}}}"#, code);
+            Error::PubSub(status(400, &body))
+        };
+        assert!(error("NOT_FOUND").is_not_found());
+        assert!(!error("NOT_FOUND").is_already_exists());
+        assert!(error("ALREADY_EXISTS").is_already_exists());
+        assert!(!error("ALREADY_EXISTS").is_not_found());
+
+        for code in [
+            "UNAVAILABLE",
+            "DEADLINE_EXCEEDED",
+            "RESOURCE_EXHAUSTED",
+            "ABORTED",
+            "INTERNAL",
+        ] {
+            assert!(error(code).is_retryable(), "{}", code);
+        }
+        for code in [
+            "INVALID_ARGUMENT",
+            "NOT_FOUND",
+            "PERMISSION_DENIED",
+            "UNKNOWN",
+        ] {
+            assert!(!error(code).is_retryable(), "{}", code);
+        }
+        assert!(Error::Timeout(Duration::from_secs(1)).is_retryable());
+        assert!(!Error::MissingProject.is_retryable());
+        assert!(!Error::MissingProject.is_not_found());
+        assert_eq!(Error::MissingProject.retry_after(), None);
+    }
+}
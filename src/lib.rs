@@ -1,10 +1,22 @@
+pub mod auth;
 pub mod client;
+mod connector;
+mod duration;
+pub mod ensure;
 pub mod error;
 pub mod message;
+pub mod name;
+pub mod proxy;
+pub mod reconcile;
+pub mod retry;
 pub mod subscription;
 pub mod topic;
 
-pub use client::Client;
+pub use auth::TokenProvider;
+pub use client::{Client, ClientBuilder, Page};
 pub use message::{EncodedMessage, FromPubSubMessage};
-pub use subscription::Subscription;
-pub use topic::Topic;
+pub use name::{IntoResourceName, SchemaName, SnapshotName, SubscriptionName, TopicName};
+pub use proxy::Proxy;
+pub use retry::RetryPolicy;
+pub use subscription::{Subscription, SubscriptionConfig};
+pub use topic::{Topic, TopicConfig};
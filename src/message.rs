@@ -26,16 +26,30 @@ impl EncodedMessage {
         self.attributes.as_ref()
     }
 
+    /// Encodes `data` as JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` cannot be serialized, see `try_new`.
     pub fn new<T: serde::Serialize>(data: &T, attributes: Option<HashMap<String, String>>) -> Self {
-        let json = serde_json::to_string(data).unwrap();
-        Self::new_binary(&json, attributes)
+        Self::try_new(data, attributes).expect("Failed to serialize message")
+    }
+
+    /// Encodes `data` as JSON, failing if it cannot be serialized, e.g. a map with
+    /// non-string keys.
+    pub fn try_new<T: serde::Serialize>(
+        data: &T,
+        attributes: Option<HashMap<String, String>>,
+    ) -> Result<Self, serde_json::Error> {
+        let json = serde_json::to_string(data)?;
+        Ok(Self::new_binary(&json, attributes))
     }
 
     pub fn new_binary<T: AsRef<[u8]> + std::marker::Sync>(
         incoming: &T,
         attributes: Option<HashMap<String, String>>,
     ) -> Self {
-        let data = base64::engine::general_purpose::STANDARD.encode(&incoming);
+        let data = base64::engine::general_purpose::STANDARD.encode(incoming);
         EncodedMessage { data, attributes }
     }
 }
@@ -0,0 +1,231 @@
+//! Validated resource names like `projects/my-project/topics/my-topic`.
+//!
+//! IDs must be 3 to 255 characters long, start with a letter, not start with `goog` and
+//! contain only letters, digits and `-_.~+%`.
+
+use crate::client::Client;
+use crate::error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Conversion into a resource name. Strings containing a `/` are parsed as full names,
+/// other strings are IDs in the client's project.
+pub trait IntoResourceName<N> {
+    fn into_resource_name(self, client: &Client) -> Result<N, error::Error>;
+}
+
+macro_rules! resource_name {
+    ($(#[$meta:meta])* $name:ident, $collection:literal) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name {
+            project: String,
+            id: String,
+        }
+
+        impl $name {
+            pub fn new(project: &str, id: &str) -> Result<Self, error::Error> {
+                if !is_valid_project(project) || !is_valid_id(id) {
+                    return Err(error::Error::InvalidResourceName(format!(
+                        concat!("projects/{}/", $collection, "/{}"),
+                        project, id
+                    )));
+                }
+                Ok($name {
+                    project: project.to_string(),
+                    id: id.to_string(),
+                })
+            }
+
+            pub fn project(&self) -> &str {
+                &self.project
+            }
+
+            pub fn id(&self) -> &str {
+                &self.id
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    concat!("projects/{}/", $collection, "/{}"),
+                    self.project, self.id
+                )
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = error::Error;
+
+            fn from_str(name: &str) -> Result<Self, Self::Err> {
+                name.strip_prefix("projects/")
+                    .and_then(|rest| rest.split_once(concat!("/", $collection, "/")))
+                    .and_then(|(project, id)| $name::new(project, id).ok())
+                    .ok_or_else(|| error::Error::InvalidResourceName(name.to_string()))
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let name = String::deserialize(deserializer)?;
+                name.parse().map_err(serde::de::Error::custom)
+            }
+        }
+
+        impl IntoResourceName<$name> for $name {
+            fn into_resource_name(self, _: &Client) -> Result<$name, error::Error> {
+                Ok(self)
+            }
+        }
+
+        impl IntoResourceName<$name> for &$name {
+            fn into_resource_name(self, _: &Client) -> Result<$name, error::Error> {
+                Ok(self.clone())
+            }
+        }
+
+        impl IntoResourceName<$name> for &str {
+            fn into_resource_name(self, client: &Client) -> Result<$name, error::Error> {
+                if self.contains('/') {
+                    self.parse()
+                } else {
+                    $name::new(&client.project()?, self)
+                }
+            }
+        }
+
+        impl IntoResourceName<$name> for String {
+            fn into_resource_name(self, client: &Client) -> Result<$name, error::Error> {
+                self.as_str().into_resource_name(client)
+            }
+        }
+
+        impl IntoResourceName<$name> for &String {
+            fn into_resource_name(self, client: &Client) -> Result<$name, error::Error> {
+                self.as_str().into_resource_name(client)
+            }
+        }
+    };
+}
+
+resource_name!(
+    /// A topic name, `projects/{project}/topics/{topic}`.
+    TopicName,
+    "topics"
+);
+resource_name!(
+    /// A subscription name, `projects/{project}/subscriptions/{subscription}`.
+    SubscriptionName,
+    "subscriptions"
+);
+resource_name!(
+    /// A snapshot name, `projects/{project}/snapshots/{snapshot}`.
+    SnapshotName,
+    "snapshots"
+);
+resource_name!(
+    /// A schema name, `projects/{project}/schemas/{schema}`.
+    SchemaName,
+    "schemas"
+);
+
+/// Deserializes a name as `None` when the resource was deleted, which the service reports
+/// with a placeholder like `_deleted-topic_` or `_deleted-schema_`.
+pub(crate) fn deserialize_deletable<'de, D, N>(deserializer: D) -> Result<Option<N>, D::Error>
+where
+    D: Deserializer<'de>,
+    N: FromStr<Err = error::Error>,
+{
+    match Option::<String>::deserialize(deserializer)?.as_deref() {
+        None => Ok(None),
+        Some(name) if name.starts_with("_deleted-") && name.ends_with('_') => Ok(None),
+        Some(name) => name.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Project IDs are checked loosely since domain scoped projects like
+/// `example.com:my-project` and emulator projects do not follow the usual rules.
+fn is_valid_project(project: &str) -> bool {
+    !project.is_empty() && !project.contains('/')
+}
+
+fn is_valid_id(id: &str) -> bool {
+    (3..=255).contains(&id.len())
+        && id.starts_with(|c: char| c.is_ascii_alphabetic())
+        && !id.starts_with("goog")
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.~+%".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_ids() {
+        assert!(TopicName::new("p", "abc").is_ok());
+        assert!(TopicName::new("p", &"a".repeat(255)).is_ok());
+        assert!(TopicName::new("p", "a-b_c.d~e+f%20").is_ok());
+        assert!(TopicName::new("p", "Goog-is-fine").is_ok());
+
+        assert!(TopicName::new("p", "ab").is_err());
+        assert!(TopicName::new("p", &"a".repeat(256)).is_err());
+        assert!(TopicName::new("p", "1abc").is_err());
+        assert!(TopicName::new("p", "-abc").is_err());
+        assert!(TopicName::new("p", "googabc").is_err());
+        assert!(TopicName::new("p", "a b c").is_err());
+        assert!(TopicName::new("p", "abc/def").is_err());
+        assert!(TopicName::new("p", "abcé").is_err());
+        assert!(TopicName::new("", "abc").is_err());
+    }
+
+    #[test]
+    fn parses_and_formats_full_names() {
+        let name: SubscriptionName = "projects/p/subscriptions/abc".parse().unwrap();
+        assert_eq!(name.project(), "p");
+        assert_eq!(name.id(), "abc");
+        assert_eq!(name.to_string(), "projects/p/subscriptions/abc");
+
+        let name: SchemaName = "projects/example.com:p/schemas/abc".parse().unwrap();
+        assert_eq!(name.project(), "example.com:p");
+    }
+
+    #[test]
+    fn rejects_malformed_full_names() {
+        assert!("projects/p/subscriptions/abc".parse::<TopicName>().is_err());
+        assert!("projects/p/topics/abc/extra".parse::<TopicName>().is_err());
+        assert!("projects/p/x/topics/abc".parse::<TopicName>().is_err());
+        assert!("projects//topics/abc".parse::<TopicName>().is_err());
+        assert!("project/p/topics/abc".parse::<TopicName>().is_err());
+        assert!("/projects/p/topics/abc".parse::<TopicName>().is_err());
+        assert!("abc".parse::<SnapshotName>().is_err());
+    }
+
+    #[test]
+    fn deserializes_deleted_resources_as_none() {
+        #[derive(serde_derive::Deserialize)]
+        struct Resource {
+            #[serde(default, deserialize_with = "deserialize_deletable")]
+            topic: Option<TopicName>,
+        }
+
+        let parse = |json| serde_json::from_str::<Resource>(json).map(|r| r.topic);
+        assert_eq!(parse(r#"{"topic":"_deleted-topic_"}"#).unwrap(), None);
+        assert_eq!(parse("{}").unwrap(), None);
+        assert_eq!(
+            parse(r#"{"topic":"projects/p/topics/abc"}"#).unwrap(),
+            Some(TopicName::new("p", "abc").unwrap())
+        );
+        assert!(parse(r#"{"topic":"abc"}"#).is_err());
+    }
+}
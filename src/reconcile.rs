@@ -0,0 +1,381 @@
+//! Declarative management of topics and subscriptions.
+//!
+//! A `Manifest` lists the desired topics and subscriptions. `Client::plan` compares it with
+//! what exists in the client's project, and `Client::apply` creates missing resources and
+//! updates drifted ones. Resources missing from the manifest are left alone.
+//!
+//! Manifests use the field names of the REST API:
+//!
+//! ```yaml
+//! topics:
+//!   - name: invoices
+//!     messageRetentionDuration: 86400s
+//! subscriptions:
+//!   - name: invoices-worker
+//!     topic: invoices
+//!     ackDeadlineSeconds: 60
+//! ```
+//!
+//! Unknown keys of the manifest and its topics and subscriptions are rejected with
+//! `Error::InvalidManifest`, so that a misspelled field is not silently ignored.
+
+use crate::client::Client;
+use crate::ensure::{drift, Drift};
+use crate::error;
+use crate::name::{SubscriptionName, TopicName};
+use crate::subscription::{Subscription, SubscriptionConfig};
+use crate::topic::{Topic, TopicConfig};
+use serde::de::IgnoredAny;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Subscription fields that cannot be changed after creation.
+const IMMUTABLE_SUBSCRIPTION_FIELDS: [&str; 3] = ["topic", "filter", "enableMessageOrdering"];
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub topics: Vec<TopicSpec>,
+    #[serde(default)]
+    pub subscriptions: Vec<SubscriptionSpec>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawTopicSpec")]
+pub struct TopicSpec {
+    /// The topic ID, e.g. `invoices`.
+    pub name: String,
+    #[serde(flatten)]
+    pub config: TopicConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawSubscriptionSpec")]
+pub struct SubscriptionSpec {
+    /// The subscription ID, e.g. `invoices-worker`.
+    pub name: String,
+    /// The topic ID in the client's project, or the full name of a topic in another
+    /// project.
+    pub topic: String,
+    #[serde(flatten)]
+    pub config: SubscriptionConfig,
+}
+
+/// A `TopicSpec` as written, with the keys no field took.
+#[derive(Deserialize)]
+struct RawTopicSpec {
+    name: String,
+    #[serde(flatten)]
+    config: TopicConfig,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl TryFrom<RawTopicSpec> for TopicSpec {
+    type Error = String;
+
+    fn try_from(raw: RawTopicSpec) -> Result<Self, Self::Error> {
+        reject_unknown("topic", &raw.name, &raw.unknown)?;
+        Ok(TopicSpec {
+            name: raw.name,
+            config: raw.config,
+        })
+    }
+}
+
+/// A `SubscriptionSpec` as written, with the keys no field took.
+#[derive(Deserialize)]
+struct RawSubscriptionSpec {
+    name: String,
+    topic: String,
+    #[serde(flatten)]
+    config: SubscriptionConfig,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl TryFrom<RawSubscriptionSpec> for SubscriptionSpec {
+    type Error = String;
+
+    fn try_from(raw: RawSubscriptionSpec) -> Result<Self, Self::Error> {
+        reject_unknown("subscription", &raw.name, &raw.unknown)?;
+        Ok(SubscriptionSpec {
+            name: raw.name,
+            topic: raw.topic,
+            config: raw.config,
+        })
+    }
+}
+
+fn reject_unknown(
+    kind: &str,
+    name: &str,
+    unknown: &BTreeMap<String, IgnoredAny>,
+) -> Result<(), String> {
+    match unknown.keys().next() {
+        Some(key) => Err(format!("unknown field `{}` in {} {}", key, kind, name)),
+        None => Ok(()),
+    }
+}
+
+impl Manifest {
+    pub fn from_json(contents: &str) -> Result<Self, error::Error> {
+        serde_json::from_str(contents).map_err(|e| error::Error::InvalidManifest(e.to_string()))
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(contents: &str) -> Result<Self, error::Error> {
+        serde_yaml::from_str(contents).map_err(|e| error::Error::InvalidManifest(e.to_string()))
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(contents: &str) -> Result<Self, error::Error> {
+        toml::from_str(contents).map_err(|e| error::Error::InvalidManifest(e.to_string()))
+    }
+
+    /// Reads a manifest, choosing the format by extension: `.json`, `.yaml`/`.yml` with
+    /// the `yaml` feature or `.toml` with the `toml` feature.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, error::Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            #[cfg(feature = "yaml")]
+            Some("yaml") | Some("yml") => Self::from_yaml(&contents),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&contents),
+            _ => Err(error::Error::InvalidManifest(format!(
+                "Unsupported manifest format: {}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// A step needed to bring the project in line with a manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    CreateTopic(TopicSpec),
+    UpdateTopic {
+        spec: TopicSpec,
+        drift: Vec<Drift>,
+    },
+    CreateSubscription(SubscriptionSpec),
+    UpdateSubscription {
+        spec: SubscriptionSpec,
+        drift: Vec<Drift>,
+    },
+    /// Drift in fields that cannot be updated. Applying a plan skips it; the subscription
+    /// has to be deleted and recreated by hand.
+    Conflict {
+        name: String,
+        drift: Vec<Drift>,
+    },
+}
+
+/// The changes `Client::apply` makes, in order: topics before the subscriptions that may
+/// refer to them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+        for change in &self.changes {
+            match change {
+                Change::CreateTopic(spec) => writeln!(f, "+ topic {}", spec.name)?,
+                Change::UpdateTopic { spec, drift } => {
+                    writeln!(f, "~ topic {}", spec.name)?;
+                    write_drift(f, drift)?;
+                }
+                Change::CreateSubscription(spec) => {
+                    writeln!(f, "+ subscription {} -> {}", spec.name, spec.topic)?
+                }
+                Change::UpdateSubscription { spec, drift } => {
+                    writeln!(f, "~ subscription {}", spec.name)?;
+                    write_drift(f, drift)?;
+                }
+                Change::Conflict { name, drift } => {
+                    writeln!(f, "! subscription {} must be recreated", name)?;
+                    write_drift(f, drift)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_drift(f: &mut fmt::Formatter, drift: &[Drift]) -> fmt::Result {
+    for drift in drift {
+        writeln!(
+            f,
+            "    {}: {} -> {}",
+            drift.field, drift.actual, drift.desired
+        )?;
+    }
+    Ok(())
+}
+
+impl Client {
+    /// Compares a manifest with the topics and subscriptions in the client's project.
+    pub async fn plan(&self, manifest: &Manifest) -> Result<Plan, error::Error> {
+        let topics: HashMap<TopicName, Topic> = self
+            .list_topics()
+            .await?
+            .into_iter()
+            .map(|topic| (topic.name.clone(), topic))
+            .collect();
+        let subscriptions: HashMap<SubscriptionName, Subscription> = self
+            .list_subscriptions()
+            .await?
+            .into_iter()
+            .map(|subscription| (subscription.name.clone(), subscription))
+            .collect();
+
+        let mut changes = Vec::new();
+        for spec in &manifest.topics {
+            match topics.get(&self.topic(&spec.name)?.name) {
+                None => changes.push(Change::CreateTopic(spec.clone())),
+                Some(topic) => {
+                    let drift = drift(&spec.config, &topic.config)?;
+                    if !drift.is_empty() {
+                        changes.push(Change::UpdateTopic {
+                            spec: spec.clone(),
+                            drift,
+                        });
+                    }
+                }
+            }
+        }
+
+        for spec in &manifest.subscriptions {
+            let subscription = match subscriptions.get(&self.subscribe(&spec.name)?.name) {
+                None => {
+                    changes.push(Change::CreateSubscription(spec.clone()));
+                    continue;
+                }
+                Some(subscription) => subscription,
+            };
+
+            let mut desired = subscription.clone();
+            desired.topic = Some(self.topic(&spec.topic)?.name);
+            desired.config = spec.config.clone();
+            let (conflicts, drift): (Vec<_>, Vec<_>) = drift(&desired, subscription)?
+                .into_iter()
+                .partition(|drift| IMMUTABLE_SUBSCRIPTION_FIELDS.contains(&drift.field.as_str()));
+            if !conflicts.is_empty() {
+                changes.push(Change::Conflict {
+                    name: spec.name.clone(),
+                    drift: conflicts,
+                });
+            }
+            if !drift.is_empty() {
+                changes.push(Change::UpdateSubscription {
+                    spec: spec.clone(),
+                    drift,
+                });
+            }
+        }
+
+        Ok(Plan { changes })
+    }
+
+    /// Makes the changes of a plan, stopping at the first failure.
+    pub async fn apply(&self, plan: &Plan) -> Result<(), error::Error> {
+        for change in &plan.changes {
+            match change {
+                Change::CreateTopic(spec) => {
+                    self.create_topic(&spec.name, spec.config.clone()).await?;
+                }
+                Change::UpdateTopic { spec, drift } => {
+                    self.topic(&spec.name)?
+                        .update(&spec.config, &update_mask(drift))
+                        .await?;
+                }
+                Change::CreateSubscription(spec) => {
+                    let topic = self.topic(&spec.topic)?;
+                    self.create_subscription(&spec.name, &topic, spec.config.clone())
+                        .await?;
+                }
+                Change::UpdateSubscription { spec, drift } => {
+                    self.subscribe(&spec.name)?
+                        .update(&spec.config, &update_mask(drift))
+                        .await?;
+                }
+                Change::Conflict { name, .. } => {
+                    log::warn!("Skipping subscription {}, it must be recreated", name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn update_mask(drift: &[Drift]) -> Vec<&str> {
+    drift.iter().map(|drift| drift.field.as_str()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_manifest(json: &str) -> String {
+        match Manifest::from_json(json) {
+            Err(error::Error::InvalidManifest(message)) => message,
+            other => panic!("expected an invalid manifest, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let message = invalid_manifest(
+            r#"{"subscriptions": [
+                {"name": "invoices-worker", "topic": "invoices", "ackDeadlineSecond": 60}
+            ]}"#,
+        );
+        assert!(message
+            .starts_with("unknown field `ackDeadlineSecond` in subscription invoices-worker"));
+
+        let message = invalid_manifest(r#"{"topics": [{"name": "invoices", "retention": "1d"}]}"#);
+        assert!(message.starts_with("unknown field `retention` in topic invoices"));
+
+        let message = invalid_manifest(r#"{"subscription": []}"#);
+        assert!(message.starts_with("unknown field `subscription`"));
+    }
+
+    #[test]
+    fn reads_known_keys() {
+        let manifest = Manifest::from_json(
+            r#"{
+                "topics": [{"name": "invoices", "labels": {"team": "billing"}}],
+                "subscriptions": [{
+                    "name": "invoices-worker",
+                    "topic": "invoices",
+                    "ackDeadlineSeconds": 60,
+                    "deadLetterPolicy": {"deadLetterTopic": "projects/p/topics/dead"}
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.topics[0].config.labels["team"], "billing");
+        let subscription = &manifest.subscriptions[0];
+        assert_eq!(subscription.topic, "invoices");
+        assert_eq!(subscription.config.ack_deadline_seconds, Some(60));
+        assert!(subscription.config.dead_letter_policy.is_some());
+    }
+}
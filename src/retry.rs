@@ -0,0 +1,145 @@
+use crate::error::{self, Code};
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+
+/// Retryable codes for `Publish`.
+const PUBLISH_CODES: [Code; 7] = [
+    Code::Aborted,
+    Code::Cancelled,
+    Code::Internal,
+    Code::ResourceExhausted,
+    Code::Unknown,
+    Code::Unavailable,
+    Code::DeadlineExceeded,
+];
+
+/// Retryable codes for `Pull`.
+const PULL_CODES: [Code; 3] = [Code::Unknown, Code::Aborted, Code::Unavailable];
+
+/// Retryable codes for `Acknowledge`.
+const ACKNOWLEDGE_CODES: [Code; 1] = [Code::Unavailable];
+
+/// Retryable codes for calls that create, change or delete resources.
+const ADMIN_CODES: [Code; 1] = [Code::Unavailable];
+
+/// How failed operations are retried.
+///
+/// Only failures that Google lists as retryable for the kind of operation are retried:
+/// publishing retries on most transient codes, while pulls, acknowledgements and
+/// administrative calls are more conservative. Connection failures are always retried.
+///
+/// The delay before the `n`th retry is `initial_backoff * multiplier^(n - 1)`, capped at
+/// `max_backoff`. With jitter, a random delay up to that value is used instead.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+    total_deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(60),
+            multiplier: 1.3,
+            jitter: true,
+            total_deadline: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that makes a single attempt.
+    pub fn never() -> Self {
+        RetryPolicy::default().with_max_attempts(1)
+    }
+
+    /// The number of attempts, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Limits the time spent on an operation across all attempts and backoffs. The
+    /// attempt running when it passes fails with `Error::Timeout`.
+    pub fn with_total_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.total_deadline = deadline;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn total_deadline(&self) -> Option<Duration> {
+        self.total_deadline
+    }
+
+    /// The delay after the given failed attempt, counting from 1.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let backoff = Duration::try_from_secs_f64(backoff)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+        if self.jitter {
+            thread_rng().gen_range(Duration::ZERO..=backoff)
+        } else {
+            backoff
+        }
+    }
+}
+
+/// The kind of call being made, which decides what is retried.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Operation {
+    Publish,
+    Pull,
+    Acknowledge,
+    Admin,
+}
+
+impl Operation {
+    fn retryable_codes(self) -> &'static [Code] {
+        match self {
+            Operation::Publish => &PUBLISH_CODES,
+            Operation::Pull => &PULL_CODES,
+            Operation::Acknowledge => &ACKNOWLEDGE_CODES,
+            Operation::Admin => &ADMIN_CODES,
+        }
+    }
+
+    /// Whether a failed attempt of this operation should be retried.
+    pub(crate) fn is_retryable(self, err: &error::Error) -> bool {
+        match err {
+            error::Error::PubSub(status) => self.retryable_codes().contains(&status.code),
+            error::Error::Http(e) => error::is_transport_error(e),
+            error::Error::Timeout(_) => self.retryable_codes().contains(&Code::DeadlineExceeded),
+            _ => false,
+        }
+    }
+}
@@ -1,17 +1,14 @@
-use crate::client::Client;
+use crate::client::{CallOptions, Client, Page};
 use crate::error;
 use crate::message::{FromPubSubMessage, Message};
-use hyper::body::Buf;
-use hyper::{Method, StatusCode};
-use lazy_static::lazy_static;
+use crate::name::{IntoResourceName, SubscriptionName, TopicName};
+use crate::retry::{Operation, RetryPolicy};
+use crate::topic::Topic;
+use hyper::Method;
+use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
-use std::env;
-
-lazy_static! {
-    static ref PUBSUB_HOST: String = env::var("PUBSUB_EMULATOR_HOST")
-        .map(|host| format!("http://{}", host))
-        .unwrap_or_else(|_| String::from("https://pubsub.googleapis.com"));
-}
+use std::collections::HashMap;
+use std::time::Duration;
 
 #[derive(Deserialize)]
 struct Response {
@@ -29,63 +26,167 @@ struct AckRequest {
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Subscription {
     #[serde(skip_serializing)]
-    pub name: String,
-    pub topic: Option<String>,
+    pub name: SubscriptionName,
+    /// `None` once the topic has been deleted.
+    #[serde(default, deserialize_with = "crate::name::deserialize_deletable")]
+    pub topic: Option<TopicName>,
+    /// The configuration as of the call that returned this subscription. Subscriptions
+    /// from `Client::subscribe` only have defaults, use `get` to fetch it.
+    #[serde(flatten)]
+    pub config: SubscriptionConfig,
 
     #[serde(skip)]
     pub(crate) client: Option<Client>,
+    #[serde(skip)]
+    pub(crate) options: CallOptions,
 }
 
-impl Subscription {
-    pub async fn acknowledge_messages(&self, ids: Vec<String>) {
-        let client = self
-            .client
-            .as_ref()
-            .expect("Subscription was not created using a client");
+/// The settable fields of a subscription.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionConfig {
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    /// How long Pub/Sub waits for an acknowledgement before redelivering, from 10 to 600
+    /// seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack_deadline_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retain_acked_messages: Option<bool>,
+    /// How long unacknowledged messages, and acknowledged ones with
+    /// `retain_acked_messages`, are kept, from 10 minutes to 7 days.
+    #[serde(
+        default,
+        with = "crate::duration::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub message_retention_duration: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_policy: Option<ExpirationPolicy>,
+    /// Only messages whose attributes match the filter are delivered. It cannot be changed
+    /// after creation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_policy: Option<DeadLetterPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_policy: Option<DeliveryRetryPolicy>,
+    /// Delivers messages with the same ordering key in order. It cannot be changed after
+    /// creation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_message_ordering: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_exactly_once_delivery: Option<bool>,
+}
+
+/// When an inactive subscription is deleted.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ExpirationPolicy {
+    /// How long the subscription may be inactive. `None` means it never expires.
+    #[serde(
+        default,
+        with = "crate::duration::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ttl: Option<Duration>,
+}
+
+/// Where messages that could not be delivered are forwarded to.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetterPolicy {
+    /// The topic to forward to.
+    pub dead_letter_topic: TopicName,
+    /// Delivery attempts before a message is forwarded, from 5 to 100.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_delivery_attempts: Option<i32>,
+}
+
+/// How Pub/Sub backs off before redelivering messages that were nacked or not
+/// acknowledged in time. Unrelated to the client side `RetryPolicy`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryRetryPolicy {
+    #[serde(
+        default,
+        with = "crate::duration::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub minimum_backoff: Option<Duration>,
+    #[serde(
+        default,
+        with = "crate::duration::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub maximum_backoff: Option<Duration>,
+}
 
-        let uri: hyper::Uri = format!("{}/v1/{}:acknowledge", *PUBSUB_HOST, self.name)
-            .parse()
-            .unwrap();
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateSubscriptionRequest<'a> {
+    subscription: SubscriptionResource<'a>,
+    update_mask: String,
+}
+
+#[derive(Serialize)]
+struct SubscriptionResource<'a> {
+    name: &'a SubscriptionName,
+    #[serde(flatten)]
+    config: &'a SubscriptionConfig,
+}
 
-        let json = serde_json::to_string(&AckRequest { ack_ids: ids }).unwrap();
+impl Subscription {
+    /// Returns a handle whose operations use `timeout` instead of the client's default.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        let mut subscription = self.clone();
+        subscription.options.timeout = Some(timeout);
+        subscription
+    }
 
-        let mut req = client.request(Method::POST, json);
-        *req.uri_mut() = uri.clone();
+    /// Returns a handle whose operations are retried with `policy` instead of the client's
+    /// default.
+    pub fn with_retry_policy(&self, policy: RetryPolicy) -> Self {
+        let mut subscription = self.clone();
+        subscription.options.retry_policy = Some(policy);
+        subscription
+    }
 
-        if let Err(e) = client.hyper_client().request(req).await {
+    pub async fn acknowledge_messages(&self, ids: Vec<String>) {
+        if let Err(e) = self.try_acknowledge_messages(ids).await {
             log::error!("Failed ACK: {}", e);
         }
     }
 
+    async fn try_acknowledge_messages(&self, ids: Vec<String>) -> Result<(), error::Error> {
+        let client = self.client()?;
+        let uri = client.uri(&self.name, ":acknowledge")?;
+        let json = serde_json::to_string(&AckRequest { ack_ids: ids })?;
+
+        client
+            .perform(
+                Method::POST,
+                uri,
+                json,
+                Operation::Acknowledge,
+                &self.options,
+            )
+            .await?;
+        Ok(())
+    }
+
     pub async fn get_messages<T: FromPubSubMessage>(
         &self,
         max_messages: i32,
     ) -> Result<Vec<(Result<T, error::Error>, String)>, error::Error> {
-        let client = self
-            .client
-            .as_ref()
-            .expect("Subscription was not created using a client");
-
-        let uri: hyper::Uri = format!("{}/v1/{}:pull", *PUBSUB_HOST, self.name)
-            .parse()
-            .unwrap();
+        let client = self.client()?;
+        let uri = client.uri(&self.name, ":pull")?;
 
         let json = format!("{{\"maxMessages\": {}}}", max_messages);
 
-        let mut req = client.request(Method::POST, json);
-        *req.uri_mut() = uri.clone();
-
-        let response = client.hyper_client().request(req).await?;
-
-        if response.status() == StatusCode::NOT_FOUND {
-            return Err(error::Error::PubSub {
-                code: 404,
-                status: "Subscription Not Found".to_string(),
-                message: self.name.clone(),
-            });
-        }
-        let body = hyper::body::aggregate(response).await?;
-        let response: Response = serde_json::from_reader(body.reader())?;
+        let body = client
+            .perform(Method::POST, uri, json, Operation::Pull, &self.options)
+            .await?;
+        let response: Response = serde_json::from_slice(&body)?;
         if let Some(e) = response.error {
             return Err(e);
         }
@@ -99,25 +200,121 @@ impl Subscription {
     }
 
     pub async fn destroy(self) -> Result<(), error::Error> {
-        let client = self
-            .client
-            .expect("Subscription was not created using a client");
+        let client = self.client()?;
+        let uri = client.uri(&self.name, "")?;
+
+        client
+            .perform(
+                Method::DELETE,
+                uri,
+                String::new(),
+                Operation::Admin,
+                &self.options,
+            )
+            .await?;
+        Ok(())
+    }
 
-        let uri: hyper::Uri = format!("{}/v1/{}", *PUBSUB_HOST, self.name)
-            .parse()
-            .unwrap();
+    /// Fetches the subscription, including its configuration.
+    pub async fn get(&self) -> Result<Subscription, error::Error> {
+        let uri = self.client()?.uri(&self.name, "")?;
+        let subscription = self
+            .perform_request(uri, Method::GET, String::new())
+            .await?;
+        Ok(self.attach(subscription))
+    }
+
+    /// Changes the fields of the configuration named in `update_mask`, e.g.
+    /// `ack_deadline_seconds` or `dead_letter_policy`, to their values in `config`.
+    pub async fn update(
+        &self,
+        config: &SubscriptionConfig,
+        update_mask: &[&str],
+    ) -> Result<Subscription, error::Error> {
+        let uri = self.client()?.uri(&self.name, "")?;
+        let json = serde_json::to_string(&UpdateSubscriptionRequest {
+            subscription: SubscriptionResource {
+                name: &self.name,
+                config,
+            },
+            update_mask: update_mask.join(","),
+        })?;
+        let subscription = self.perform_request(uri, Method::PATCH, json).await?;
+        Ok(self.attach(subscription))
+    }
 
-        let mut req = client.request(Method::DELETE, "");
-        *req.uri_mut() = uri.clone();
+    async fn perform_request<U: DeserializeOwned>(
+        &self,
+        uri: hyper::Uri,
+        method: Method,
+        json: String,
+    ) -> Result<U, error::Error> {
+        let body = self
+            .client()?
+            .perform(method, uri, json, Operation::Admin, &self.options)
+            .await?;
+        serde_json::from_slice(&body).map_err(|e| e.into())
+    }
 
-        if let Err(e) = client.hyper_client().request(req).await {
-            Err(e.into())
-        } else {
-            Ok(())
+    /// Gives a subscription returned by the API this handle's client and call options.
+    fn attach(&self, mut subscription: Subscription) -> Subscription {
+        subscription.client = self.client.clone();
+        subscription.options = self.options.clone();
+        subscription
+    }
+
+    /// The client the subscription was created with, which deserialized subscriptions
+    /// lack.
+    pub fn client(&self) -> Result<&Client, error::Error> {
+        self.client.as_ref().ok_or(error::Error::MissingClient)
+    }
+}
+
+impl Client {
+    /// Creates a subscription to `topic`, by ID in the client's project or by full name.
+    /// The topic may be in another project.
+    pub async fn create_subscription<N: IntoResourceName<SubscriptionName>>(
+        &self,
+        name: N,
+        topic: &Topic,
+        config: SubscriptionConfig,
+    ) -> Result<Subscription, error::Error> {
+        let mut subscription = self.subscribe(name)?;
+        subscription.topic = Some(topic.name.clone());
+        subscription.config = config;
+
+        let uri = self.uri(&subscription.name, "")?;
+        let json = serde_json::to_string(&subscription)?;
+        let created = subscription.perform_request(uri, Method::PUT, json).await?;
+        Ok(subscription.attach(created))
+    }
+
+    /// Lists one page of the subscriptions in the client's project.
+    pub async fn list_subscriptions_page(
+        &self,
+        page_size: Option<u32>,
+        page_token: Option<String>,
+    ) -> Result<Page<Subscription>, error::Error> {
+        let collection = format!("projects/{}/subscriptions", self.project()?);
+        let mut page: Page<Subscription> =
+            self.list_page(&collection, page_size, page_token).await?;
+        for subscription in &mut page.items {
+            subscription.client = Some(self.clone());
         }
+        Ok(page)
     }
 
-    pub fn client(&self) -> &Client {
-        self.client.as_ref().unwrap()
+    /// Lists all subscriptions in the client's project, following page tokens.
+    pub async fn list_subscriptions(&self) -> Result<Vec<Subscription>, error::Error> {
+        let mut subscriptions = Vec::new();
+        let mut page_token = None;
+        loop {
+            let page = self.list_subscriptions_page(None, page_token).await?;
+            subscriptions.extend(page.items);
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                return Ok(subscriptions);
+            }
+        }
     }
 }
@@ -1,28 +1,111 @@
-use crate::client::Client;
+use crate::client::{CallOptions, Client, Page};
 use crate::error;
+use crate::name::{IntoResourceName, SchemaName, SubscriptionName, TopicName};
+use crate::retry::{Operation, RetryPolicy};
 use crate::subscription::*;
 use crate::EncodedMessage;
-use hyper::body::Buf;
-use hyper::{Method, StatusCode};
-use lazy_static::lazy_static;
+use hyper::Method;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
-use std::env;
+use std::collections::HashMap;
+use std::time::Duration;
 
-lazy_static! {
-    static ref PUBSUB_HOST: String = env::var("PUBSUB_EMULATOR_HOST")
-        .map(|host| format!("http://{}", host))
-        .unwrap_or_else(|_| String::from("https://pubsub.googleapis.com"));
-}
-
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Topic {
-    pub name: String,
+    pub name: TopicName,
+    /// The configuration as of the call that returned this topic. Topics from
+    /// `Client::topic` only have defaults, use `get` to fetch it.
+    #[serde(flatten)]
+    pub config: TopicConfig,
 
     #[serde(skip)]
     pub(crate) client: Option<Client>,
+    #[serde(skip)]
+    pub(crate) options: CallOptions,
+}
+
+/// The settable fields of a topic.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicConfig {
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    /// How long published messages are kept, from 10 minutes to 31 days.
+    #[serde(
+        default,
+        with = "crate::duration::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub message_retention_duration: Option<Duration>,
+    /// The Cloud KMS key protecting access to messages, as
+    /// `projects/*/locations/*/keyRings/*/cryptoKeys/*`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kms_key_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_storage_policy: Option<MessageStoragePolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_settings: Option<SchemaSettings>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStoragePolicy {
+    /// The regions messages may be stored in, e.g. `us-east1`.
+    #[serde(default)]
+    pub allowed_persistence_regions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enforce_in_transit: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaSettings {
+    /// The schema messages are validated against, `None` once it has been deleted.
+    #[serde(
+        default,
+        deserialize_with = "crate::name::deserialize_deletable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub schema: Option<SchemaName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_revision_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_revision_id: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Encoding {
+    EncodingUnspecified,
+    Json,
+    Binary,
+}
+
+/// The most messages a publish request may contain.
+const MAX_PUBLISH_MESSAGES: usize = 1000;
+
+/// The largest publish request accepted, in bytes.
+const MAX_PUBLISH_BYTES: usize = 10 * 1000 * 1000;
+
+/// The bytes of a publish request besides its messages, `{"messages":[]}`.
+const PUBLISH_REQUEST_OVERHEAD: usize = 15;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateTopicRequest<'a> {
+    topic: TopicResource<'a>,
+    update_mask: String,
+}
+
+#[derive(Serialize)]
+struct TopicResource<'a> {
+    name: &'a TopicName,
+    #[serde(flatten)]
+    config: &'a TopicConfig,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -37,24 +120,41 @@ pub struct PublishMessageRequest {
 }
 
 impl Topic {
+    /// Returns a handle whose operations use `timeout` instead of the client's default.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        let mut topic = self.clone();
+        topic.options.timeout = Some(timeout);
+        topic
+    }
+
+    /// Returns a handle whose operations are retried with `policy` instead of the client's
+    /// default.
+    pub fn with_retry_policy(&self, policy: RetryPolicy) -> Self {
+        let mut topic = self.clone();
+        topic.options.retry_policy = Some(policy);
+        topic
+    }
+
     pub async fn subscribe(&self) -> Result<Subscription, error::Error> {
-        let client = self.client.clone();
+        let client = self.client()?;
 
         let new_subscription = Subscription {
-            name: self.new_subscription_name(),
+            name: self.new_subscription_name()?,
             topic: Some(self.name.clone()),
+            config: SubscriptionConfig::default(),
             client: None,
+            options: CallOptions::default(),
         };
 
-        let uri: hyper::Uri = format!("{}/v1/{}", *PUBSUB_HOST, new_subscription.name)
-            .parse()
-            .unwrap();
+        let uri = client.uri(&new_subscription.name, "")?;
+        let json = serde_json::to_string(&new_subscription)?;
 
-        let mut sub = self
-            .perform_request::<Subscription, Subscription>(uri, Method::PUT, new_subscription)
+        let mut sub: Subscription = self
+            .perform_request(uri, Method::PUT, json, Operation::Admin)
             .await?;
 
-        sub.client = client.clone();
+        sub.client = Some(client.clone());
+        sub.options = self.options.clone();
         Ok(sub)
     }
 
@@ -62,77 +162,273 @@ impl Topic {
         &self,
         data: T,
     ) -> Result<PublishMessageResponse, error::Error> {
-        self.publish_message(EncodedMessage::new(&data, None)).await
+        self.publish_message(EncodedMessage::try_new(&data, None)?)
+            .await
     }
 
     pub async fn publish_message(
         &self,
         message: EncodedMessage,
     ) -> Result<PublishMessageResponse, error::Error> {
-        let uri: hyper::Uri = format!("{}/v1/{}:publish", *PUBSUB_HOST, self.name)
-            .parse()
-            .unwrap();
+        let uri = self.client()?.uri(&self.name, ":publish")?;
 
         let payload = PublishMessageRequest {
             messages: vec![message],
         };
+        let json = serde_json::to_string(&payload)?;
 
-        self.perform_request::<PublishMessageRequest, PublishMessageResponse>(
-            uri,
-            Method::POST,
-            payload,
-        )
-        .await
+        self.perform_request(uri, Method::POST, json, Operation::Publish)
+            .await
+    }
+
+    /// Publishes messages, returning their IDs in the same order. Messages are sent in as
+    /// few requests as the limits of 1000 messages and 10 MB per request allow, one after
+    /// the other. If a request fails, the messages of earlier requests stay published.
+    pub async fn publish_messages(
+        &self,
+        messages: Vec<EncodedMessage>,
+    ) -> Result<Vec<String>, error::Error> {
+        let messages = messages
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut message_ids = Vec::with_capacity(messages.len());
+        for batch in publish_batches(&messages) {
+            message_ids.extend(self.publish_batch(batch).await?);
+        }
+        Ok(message_ids)
+    }
+
+    /// Publishes messages already serialized as JSON in a single request.
+    async fn publish_batch(&self, messages: &[String]) -> Result<Vec<String>, error::Error> {
+        let uri = self.client()?.uri(&self.name, ":publish")?;
+        let json = format!("{{\"messages\":[{}]}}", messages.join(","));
+        let response: PublishMessageResponse = self
+            .perform_request(uri, Method::POST, json, Operation::Publish)
+            .await?;
+        Ok(response.message_ids)
+    }
+
+    /// Fetches the topic, including its configuration.
+    pub async fn get(&self) -> Result<Topic, error::Error> {
+        let uri = self.client()?.uri(&self.name, "")?;
+        let topic = self
+            .perform_request(uri, Method::GET, String::new(), Operation::Admin)
+            .await?;
+        Ok(self.attach(topic))
+    }
+
+    /// Changes the fields of the configuration named in `update_mask`, e.g. `labels` or
+    /// `message_retention_duration`, to their values in `config`.
+    pub async fn update(
+        &self,
+        config: &TopicConfig,
+        update_mask: &[&str],
+    ) -> Result<Topic, error::Error> {
+        let uri = self.client()?.uri(&self.name, "")?;
+        let json = serde_json::to_string(&UpdateTopicRequest {
+            topic: TopicResource {
+                name: &self.name,
+                config,
+            },
+            update_mask: update_mask.join(","),
+        })?;
+        let topic = self
+            .perform_request(uri, Method::PATCH, json, Operation::Admin)
+            .await?;
+        Ok(self.attach(topic))
+    }
+
+    /// Deletes the topic. Its subscriptions are not deleted, but stop receiving messages.
+    pub async fn delete(self) -> Result<(), error::Error> {
+        let client = self.client()?;
+        let uri = client.uri(&self.name, "")?;
+        client
+            .perform(
+                Method::DELETE,
+                uri,
+                String::new(),
+                Operation::Admin,
+                &self.options,
+            )
+            .await?;
+        Ok(())
     }
 
-    async fn perform_request<T: serde::Serialize, U: DeserializeOwned + Clone>(
+    async fn perform_request<U: DeserializeOwned>(
         &self,
         uri: hyper::Uri,
         method: Method,
-        data: T,
+        json: String,
+        operation: Operation,
     ) -> Result<U, error::Error> {
-        let client = self
-            .client
-            .clone()
-            .expect("Topic must be created using a client");
-
-        let json = serde_json::to_string(&data).expect("Failed to serialize request body.");
-        let mut req = client.request(method, json);
-        *req.uri_mut() = uri;
-
-        let response = client.hyper_client().request(req).await?;
-        match response.status() {
-            StatusCode::NOT_FOUND => Err(error::Error::PubSub {
-                code: 404,
-                status: "Topic Not Found".to_string(),
-                message: self.name.clone(),
-            }),
-            StatusCode::OK => {
-                let body = hyper::body::aggregate(response).await?;
-                serde_json::from_reader(body.reader()).map_err(|e| e.into())
-            }
-            code => {
-                let body = hyper::body::aggregate(response).await?;
-                let mut buf = String::new();
-                use std::io::Read;
-                body.reader().read_to_string(&mut buf)?;
-                Err(error::Error::PubSub {
-                    code: code.as_u16() as i32,
-                    status: "Error occurred attempting to subscribe".to_string(),
-                    message: buf,
-                })
-            }
-        }
+        let body = self
+            .client()?
+            .perform(method, uri, json, operation, &self.options)
+            .await?;
+        serde_json::from_slice(&body).map_err(|e| e.into())
     }
 
-    fn new_subscription_name(&self) -> String {
-        let project = self.client.clone().unwrap().project();
+    /// Gives a topic returned by the API this handle's client and call options.
+    fn attach(&self, mut topic: Topic) -> Topic {
+        topic.client = self.client.clone();
+        topic.options = self.options.clone();
+        topic
+    }
+
+    /// The client the topic was created with, which deserialized topics lack.
+    pub fn client(&self) -> Result<&Client, error::Error> {
+        self.client.as_ref().ok_or(error::Error::MissingClient)
+    }
+
+    fn new_subscription_name(&self) -> Result<SubscriptionName, error::Error> {
+        let project = self.client()?.project()?;
         let slug = thread_rng()
             .sample_iter(&Alphanumeric)
             .take(30)
             .map(char::from)
             .collect::<String>();
 
-        format!("projects/{}/subscriptions/RST{}", project, slug)
+        SubscriptionName::new(&project, &format!("RST{}", slug))
+    }
+}
+
+/// Splits messages serialized as JSON into as few publish requests as the limits allow,
+/// keeping their order. A message over the size limit on its own is sent alone.
+fn publish_batches(messages: &[String]) -> Vec<&[String]> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut bytes = PUBLISH_REQUEST_OVERHEAD;
+    for (index, message) in messages.iter().enumerate() {
+        if index > start {
+            // Including the separating comma
+            let grown = bytes + 1 + message.len();
+            if index - start == MAX_PUBLISH_MESSAGES || grown > MAX_PUBLISH_BYTES {
+                batches.push(&messages[start..index]);
+                start = index;
+                bytes = PUBLISH_REQUEST_OVERHEAD + message.len();
+            } else {
+                bytes = grown;
+            }
+        } else {
+            bytes += message.len();
+        }
+    }
+    if start < messages.len() {
+        batches.push(&messages[start..]);
+    }
+    batches
+}
+
+impl Client {
+    /// Creates a topic, by ID in the client's project or by full name.
+    pub async fn create_topic<N: IntoResourceName<TopicName>>(
+        &self,
+        name: N,
+        config: TopicConfig,
+    ) -> Result<Topic, error::Error> {
+        let topic = self.topic(name)?;
+        let uri = self.uri(&topic.name, "")?;
+        let json = serde_json::to_string(&config)?;
+        let created = topic
+            .perform_request(uri, Method::PUT, json, Operation::Admin)
+            .await?;
+        Ok(topic.attach(created))
+    }
+
+    /// Lists one page of the topics in the client's project.
+    pub async fn list_topics_page(
+        &self,
+        page_size: Option<u32>,
+        page_token: Option<String>,
+    ) -> Result<Page<Topic>, error::Error> {
+        let collection = format!("projects/{}/topics", self.project()?);
+        let mut page: Page<Topic> = self.list_page(&collection, page_size, page_token).await?;
+        for topic in &mut page.items {
+            topic.client = Some(self.clone());
+        }
+        Ok(page)
+    }
+
+    /// Lists all topics in the client's project, following page tokens.
+    pub async fn list_topics(&self) -> Result<Vec<Topic>, error::Error> {
+        let mut topics = Vec::new();
+        let mut page_token = None;
+        loop {
+            let page = self.list_topics_page(None, page_token).await?;
+            topics.extend(page.items);
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                return Ok(topics);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(messages: &[String]) -> Vec<usize> {
+        publish_batches(messages)
+            .iter()
+            .map(|batch| batch.len())
+            .collect()
+    }
+
+    #[test]
+    fn publish_request_overhead_matches_the_request_format() {
+        assert_eq!(PUBLISH_REQUEST_OVERHEAD, r#"{"messages":[]}"#.len());
+    }
+
+    #[test]
+    fn publish_batches_cut_at_the_message_limit() {
+        let messages = vec![String::from("{}"); 2 * MAX_PUBLISH_MESSAGES + 1];
+        assert_eq!(sizes(&messages[..MAX_PUBLISH_MESSAGES]), [1000]);
+        assert_eq!(sizes(&messages[..MAX_PUBLISH_MESSAGES + 1]), [1000, 1]);
+        assert_eq!(sizes(&messages), [1000, 1000, 1]);
+        assert!(sizes(&[]).is_empty());
+    }
+
+    #[test]
+    fn publish_batches_cut_at_the_size_limit() {
+        // Two messages of this size and the comma between them fill a request exactly.
+        let fitting = (MAX_PUBLISH_BYTES - PUBLISH_REQUEST_OVERHEAD - 1) / 2;
+        assert_eq!(
+            PUBLISH_REQUEST_OVERHEAD + 2 * fitting + 1,
+            MAX_PUBLISH_BYTES
+        );
+
+        let messages = vec!["x".repeat(fitting); 3];
+        assert_eq!(sizes(&messages), [2, 1]);
+        let messages = vec!["x".repeat(fitting + 1); 3];
+        assert_eq!(sizes(&messages), [1, 1, 1]);
+    }
+
+    #[test]
+    fn publish_batches_send_oversized_messages_alone() {
+        let messages = vec![
+            String::from("{}"),
+            "x".repeat(MAX_PUBLISH_BYTES),
+            String::from("{}"),
+            String::from("{}"),
+        ];
+        let batches = publish_batches(&messages);
+        assert_eq!(sizes(&messages), [1, 1, 2]);
+        assert_eq!(batches[1][0].len(), MAX_PUBLISH_BYTES);
+    }
+
+    #[test]
+    fn topic_with_deleted_schema_deserializes() {
+        let topic: Topic = serde_json::from_str(
+            r#"{
+                "name": "projects/p/topics/abc",
+                "schemaSettings": {"schema": "_deleted-schema_", "encoding": "JSON"}
+            }"#,
+        )
+        .unwrap();
+        let settings = topic.config.schema_settings.unwrap();
+        assert_eq!(settings.schema, None);
+        assert_eq!(settings.encoding, Some(Encoding::Json));
     }
 }
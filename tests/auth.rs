@@ -0,0 +1,355 @@
+mod common;
+
+use async_trait::async_trait;
+use base64::Engine;
+use cloud_pubsub::auth::{self, MetadataServer, ServiceAccount, Token, TokenProvider};
+use cloud_pubsub::client::HyperClient;
+use cloud_pubsub::error::{Code, Error};
+use cloud_pubsub::Client;
+use common::FakeServer;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Verifier;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+#[tokio::test]
+async fn impersonated_file_requests_cloud_platform_for_service_account_source() {
+    let server = FakeServer::start(|request| {
+        if request.uri.ends_with(":generateAccessToken") {
+            (200, r#"{"accessToken":"impersonated"}"#.to_string())
+        } else {
+            common::token_response("base")
+        }
+    });
+    let file = serde_json::json!({
+        "type": "impersonated_service_account",
+        "service_account_impersonation_url": format!(
+            "{}/v1/projects/-/serviceAccounts/target@test-project.iam.gserviceaccount.com:generateAccessToken",
+            server.url()
+        ),
+        "source_credentials": serde_json::from_str::<serde_json::Value>(
+            &common::service_account_json(&format!("{}/token", server.url()))
+        ).unwrap(),
+    });
+    let provider = auth::from_json(file.to_string()).unwrap();
+
+    let client = common::client(&server, "test-project");
+    let token = provider.token(&client.hyper_client()).await.unwrap();
+    assert_eq!(token.access_token(), "impersonated");
+
+    let requests = server.requests();
+    let assertion = requests[0].form_value("assertion").unwrap();
+    assert_eq!(
+        common::jwt_part(&assertion, 1)["scope"],
+        CLOUD_PLATFORM_SCOPE
+    );
+    assert_eq!(requests[1].header("authorization"), Some("Bearer base"));
+}
+
+#[tokio::test]
+async fn self_signed_jwt_names_the_signing_key() {
+    let server = FakeServer::start(|_| (500, String::new()));
+    let json = common::service_account_json(&format!("{}/token", server.url()));
+    let provider = json
+        .parse::<ServiceAccount>()
+        .unwrap()
+        .with_self_signed_jwt();
+
+    let client = common::client(&server, "test-project");
+    let token = provider.token(&client.hyper_client()).await.unwrap();
+    let jwt = token.access_token();
+
+    let header = common::jwt_part(jwt, 0);
+    assert_eq!(header["alg"], "RS256");
+    assert_eq!(header["kid"], "test-key-id");
+    let claims = common::jwt_part(jwt, 1);
+    assert_eq!(claims["aud"], "https://pubsub.googleapis.com/");
+    assert_eq!(claims["iss"], "tester@test-project.iam.gserviceaccount.com");
+
+    let (input, signature) = jwt.rsplit_once('.').unwrap();
+    let key: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let key = PKey::private_key_from_pem(key["private_key"].as_str().unwrap().as_bytes()).unwrap();
+    let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(signature)
+        .unwrap();
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key).unwrap();
+    verifier.update(input.as_bytes()).unwrap();
+    assert!(verifier.verify(&signature).unwrap());
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn metadata_server_host_can_be_overridden() {
+    let server = FakeServer::start(|request| {
+        if request.header("metadata-flavor") != Some("Google") {
+            return (403, String::new());
+        }
+        match request.uri.as_str() {
+            "/computeMetadata/v1/instance/service-accounts/default/token" => {
+                common::token_response("metadata")
+            }
+            "/computeMetadata/v1/project/project-id" => (200, "metadata-project".to_string()),
+            _ => (404, String::new()),
+        }
+    });
+
+    let client = Client::builder()
+        .token_provider(Arc::new(
+            MetadataServer::new().with_host(server.addr.to_string()),
+        ))
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(client.project().unwrap(), "metadata-project");
+    client.refresh_token().await.unwrap();
+    assert!(server.requests().iter().any(
+        |request| request.uri == "/computeMetadata/v1/instance/service-accounts/default/token"
+    ));
+}
+
+#[tokio::test]
+async fn authorized_user_exchanges_refresh_token_at_token_uri() {
+    let server = FakeServer::start(|request| {
+        if request.uri == "/token" {
+            common::token_response("user-token")
+        } else {
+            (200, r#"{"messageIds":["1"]}"#.to_string())
+        }
+    });
+    let file = json!({
+        "type": "authorized_user",
+        "client_id": "client-id",
+        "client_secret": "client-secret",
+        "refresh_token": "refresh-token",
+        "quota_project_id": "user-project",
+        "token_uri": format!("{}/token", server.url()),
+    });
+    let client = Client::builder()
+        .endpoint(server.url())
+        .token_provider(auth::from_json(file.to_string()).unwrap())
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(client.project().unwrap(), "user-project");
+    client
+        .topic("events")
+        .unwrap()
+        .publish("hello")
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    let exchange = &requests[0];
+    assert_eq!(exchange.uri, "/token");
+    assert_eq!(
+        exchange.form_value("grant_type").as_deref(),
+        Some("refresh_token")
+    );
+    assert_eq!(
+        exchange.form_value("refresh_token").as_deref(),
+        Some("refresh-token")
+    );
+    let publish = requests.last().unwrap();
+    assert_eq!(
+        publish.uri,
+        "/v1/projects/user-project/topics/events:publish"
+    );
+    assert_eq!(publish.header("authorization"), Some("Bearer user-token"));
+}
+
+#[tokio::test]
+async fn external_account_exchanges_subject_token_at_sts() {
+    let server = FakeServer::start(|request| match request.uri.as_str() {
+        "/subject" => (200, r#"{"id_token":"subject-jwt"}"#.to_string()),
+        "/sts" => common::token_response("federated"),
+        _ => (200, r#"{"accessToken":"impersonated"}"#.to_string()),
+    });
+    let file = json!({
+        "type": "external_account",
+        "audience": "//iam.googleapis.com/projects/1/locations/global/workloadIdentityPools/pool/providers/provider",
+        "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+        "token_url": format!("{}/sts", server.url()),
+        "credential_source": {
+            "url": format!("{}/subject", server.url()),
+            "headers": {"Metadata": "True"},
+            "format": {"type": "json", "subject_token_field_name": "id_token"},
+        },
+        "service_account_impersonation_url": format!(
+            "{}/v1/projects/-/serviceAccounts/runner@test-project.iam.gserviceaccount.com:generateAccessToken",
+            server.url()
+        ),
+    });
+    let provider = auth::from_json(file.to_string()).unwrap();
+
+    let client = common::client(&server, "test-project");
+    let token = provider.token(&client.hyper_client()).await.unwrap();
+    assert_eq!(token.access_token(), "impersonated");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("metadata"), Some("True"));
+    let exchange = &requests[1];
+    assert_eq!(exchange.uri, "/sts");
+    assert_eq!(
+        exchange.form_value("grant_type").as_deref(),
+        Some("urn:ietf:params:oauth:grant-type:token-exchange")
+    );
+    assert_eq!(
+        exchange.form_value("subject_token").as_deref(),
+        Some("subject-jwt")
+    );
+    assert_eq!(
+        exchange.form_value("scope").as_deref(),
+        Some(CLOUD_PLATFORM_SCOPE)
+    );
+    assert_eq!(
+        requests[2].uri,
+        "/v1/projects/-/serviceAccounts/runner@test-project.iam.gserviceaccount.com:generateAccessToken"
+    );
+    assert_eq!(
+        requests[2].header("authorization"),
+        Some("Bearer federated")
+    );
+}
+
+#[tokio::test]
+async fn external_account_reads_subject_token_from_file() {
+    let server = FakeServer::start(|_| common::token_response("federated"));
+    let path = std::env::temp_dir().join(format!("subject-token-{}", std::process::id()));
+    std::fs::write(&path, "file-subject\n").unwrap();
+    let file = json!({
+        "type": "external_account",
+        "audience": "audience",
+        "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+        "token_url": format!("{}/sts", server.url()),
+        "credential_source": {"file": path},
+    });
+    let provider = auth::from_json(file.to_string()).unwrap();
+
+    let client = common::client(&server, "test-project");
+    let token = provider.token(&client.hyper_client()).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(token.access_token(), "federated");
+    assert_eq!(
+        server.requests()[0].form_value("subject_token").as_deref(),
+        Some("file-subject")
+    );
+}
+
+/// Hands out `token-1`, `token-2`, ... without expiry, taking a while for each refresh.
+#[derive(Default)]
+struct NumberedTokens {
+    fetched: AtomicUsize,
+}
+
+#[async_trait]
+impl TokenProvider for NumberedTokens {
+    async fn token(&self, _client: &HyperClient) -> Result<Token, Error> {
+        let n = self.fetched.fetch_add(1, Ordering::SeqCst) + 1;
+        if n > 1 {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        Ok(Token::new(
+            format!("token-{}", n),
+            "Bearer".to_string(),
+            None,
+        ))
+    }
+}
+
+/// A client for `server` using `tokens`, with its first token already fetched.
+async fn numbered_token_client(server: &FakeServer, tokens: &Arc<NumberedTokens>) -> Client {
+    Client::builder()
+        .endpoint(server.url())
+        .project("test-project".to_string())
+        .token_provider(tokens.clone())
+        .build()
+        .await
+        .unwrap()
+}
+
+fn publish_authorizations(server: &FakeServer) -> Vec<String> {
+    server
+        .requests()
+        .iter()
+        .filter_map(|request| request.header("authorization").map(String::from))
+        .collect()
+}
+
+const UNAUTHENTICATED: &str = r#"{"error":{"code":401,"status":"UNAUTHENTICATED"}}"#;
+
+#[tokio::test]
+async fn unauthenticated_request_is_replayed_once_with_a_refreshed_token() {
+    let server = FakeServer::start(|request| match request.header("authorization") {
+        Some("Bearer token-1") => (401, UNAUTHENTICATED.to_string()),
+        _ => (200, r#"{"messageIds":["1"]}"#.to_string()),
+    });
+    let tokens = Arc::new(NumberedTokens::default());
+    let client = numbered_token_client(&server, &tokens).await;
+
+    let response = client
+        .topic("events")
+        .unwrap()
+        .publish("hello")
+        .await
+        .unwrap();
+    assert_eq!(response.message_ids, ["1"]);
+    assert_eq!(tokens.fetched.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        publish_authorizations(&server),
+        ["Bearer token-1", "Bearer token-2"]
+    );
+}
+
+#[tokio::test]
+async fn second_unauthenticated_response_is_returned_without_another_replay() {
+    let server = FakeServer::start(|_| (401, UNAUTHENTICATED.to_string()));
+    let tokens = Arc::new(NumberedTokens::default());
+    let client = numbered_token_client(&server, &tokens).await;
+
+    let err = client
+        .topic("events")
+        .unwrap()
+        .publish("hello")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status().unwrap().code, Code::Unauthenticated);
+    assert_eq!(tokens.fetched.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        publish_authorizations(&server),
+        ["Bearer token-1", "Bearer token-2"]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_unauthenticated_requests_share_one_refresh() {
+    let server = FakeServer::start(|request| match request.header("authorization") {
+        Some("Bearer token-1") => (401, UNAUTHENTICATED.to_string()),
+        _ => (200, r#"{"messageIds":["1"]}"#.to_string()),
+    });
+    let tokens = Arc::new(NumberedTokens::default());
+    let client = numbered_token_client(&server, &tokens).await;
+
+    let publishes: Vec<_> = (0..8)
+        .map(|_| {
+            let topic = client.topic("events").unwrap();
+            tokio::spawn(async move { topic.publish("hello").await })
+        })
+        .collect();
+    for publish in publishes {
+        publish.await.unwrap().unwrap();
+    }
+
+    assert_eq!(tokens.fetched.load(Ordering::SeqCst), 2);
+    let authorizations = publish_authorizations(&server);
+    let stale = authorizations
+        .iter()
+        .filter(|authorization| *authorization == "Bearer token-1")
+        .count();
+    assert_eq!(stale, 8);
+    assert_eq!(authorizations.len(), 16);
+}
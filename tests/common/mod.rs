@@ -0,0 +1,154 @@
+//! A local HTTP server standing in for Google endpoints in tests.
+
+#![allow(dead_code)]
+
+use cloud_pubsub::auth::StaticToken;
+use cloud_pubsub::auth::Token;
+use cloud_pubsub::Client;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A request received by a `FakeServer`.
+#[derive(Clone, Debug)]
+pub struct Recorded {
+    pub method: String,
+    /// The path and query, e.g. `/v1/projects/p/topics/t:publish`.
+    pub uri: String,
+    pub headers: hyper::HeaderMap,
+    pub body: String,
+}
+
+impl Recorded {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+
+    pub fn form(&self) -> Vec<(String, String)> {
+        serde_urlencoded::from_str(&self.body).unwrap()
+    }
+
+    pub fn form_value(&self, name: &str) -> Option<String> {
+        self.form()
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Answers every request with the status and JSON body returned by the handler, and
+/// records the requests.
+pub struct FakeServer {
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl FakeServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Recorded) -> (u16, String) + Send + Sync + 'static,
+    {
+        Self::start_delayed(Duration::ZERO, handler)
+    }
+
+    /// Like `start`, but waits for `delay` before answering each request.
+    pub fn start_delayed<F>(delay: Duration, handler: F) -> Self
+    where
+        F: Fn(&Recorded) -> (u16, String) + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&requests);
+        let make_service = make_service_fn(move |_| {
+            let handler = Arc::clone(&handler);
+            let recorder = Arc::clone(&recorder);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let handler = Arc::clone(&handler);
+                    let recorder = Arc::clone(&recorder);
+                    async move {
+                        let (parts, body) = request.into_parts();
+                        let body = hyper::body::to_bytes(body).await.unwrap();
+                        let recorded = Recorded {
+                            method: parts.method.to_string(),
+                            uri: parts.uri.to_string(),
+                            headers: parts.headers,
+                            body: String::from_utf8_lossy(&body).into_owned(),
+                        };
+                        let (status, body) = handler(&recorded);
+                        recorder.lock().unwrap().push(recorded);
+                        tokio::time::sleep(delay).await;
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .status(status)
+                                .header("Content-Type", "application/json")
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        FakeServer { addr, requests }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// A token endpoint response.
+pub fn token_response(access_token: &str) -> (u16, String) {
+    (
+        200,
+        serde_json::json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": 3600,
+        })
+        .to_string(),
+    )
+}
+
+/// A client for the fake Pub/Sub endpoint at `server`.
+pub fn client(server: &FakeServer, project: &str) -> Client {
+    Client::emulator(server.addr.to_string(), project.to_string()).unwrap()
+}
+
+/// A token provider handing out a fixed token.
+pub fn static_token(access_token: &str) -> Arc<StaticToken> {
+    Arc::new(StaticToken::new(Token::new(
+        access_token.to_string(),
+        "Bearer".to_string(),
+        None,
+    )))
+}
+
+/// The test service account key, with its token endpoint pointed at `token_uri`.
+pub fn service_account_json(token_uri: &str) -> String {
+    include_str!("../fixtures/service_account.json").replace("TOKEN_URI", token_uri)
+}
+
+/// Decodes the claims of a JWT.
+pub fn jwt_part(jwt: &str, index: usize) -> serde_json::Value {
+    use base64::Engine;
+    let part = jwt.split('.').nth(index).unwrap().trim_end_matches('=');
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(part)
+        .unwrap();
+    serde_json::from_slice(&json).unwrap()
+}
@@ -0,0 +1,23 @@
+use cloud_pubsub::error::Error;
+use cloud_pubsub::Client;
+
+#[test]
+fn emulator_rejects_an_empty_project() {
+    let result = Client::emulator("localhost:8085".to_string(), String::new());
+    assert!(matches!(result, Err(Error::MissingProject)));
+}
+
+#[tokio::test]
+async fn emulator_host_is_taken_from_the_builder() {
+    let client = Client::builder()
+        .emulator("localhost:8085".to_string())
+        .project("test-project".to_string())
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(client.endpoint(), "http://localhost:8085");
+    assert_eq!(
+        client.topic("abc").unwrap().name.to_string(),
+        "projects/test-project/topics/abc"
+    );
+}
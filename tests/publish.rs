@@ -0,0 +1,38 @@
+mod common;
+
+use cloud_pubsub::EncodedMessage;
+use common::FakeServer;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[tokio::test]
+async fn publish_messages_keeps_ids_in_input_order_across_requests() {
+    let next_id = AtomicUsize::new(0);
+    let server = FakeServer::start(move |request| {
+        let count = request.json()["messages"].as_array().unwrap().len();
+        let first = next_id.fetch_add(count, Ordering::SeqCst);
+        let ids: Vec<String> = (first..first + count).map(|id| id.to_string()).collect();
+        (200, serde_json::json!({ "messageIds": ids }).to_string())
+    });
+    let topic = common::client(&server, "test-project")
+        .topic("events")
+        .unwrap();
+
+    let messages = (0..2500).map(|i| EncodedMessage::new(&i, None)).collect();
+    let ids = topic.publish_messages(messages).await.unwrap();
+
+    let expected: Vec<String> = (0..2500).map(|id| id.to_string()).collect();
+    assert_eq!(ids, expected);
+    let requests = server.requests();
+    let sizes: Vec<usize> = requests
+        .iter()
+        .map(|request| request.json()["messages"].as_array().unwrap().len())
+        .collect();
+    assert_eq!(sizes, [1000, 1000, 500]);
+    // Each request carries its slice of the input, in order.
+    let first = &requests[1].json()["messages"][0];
+    let message: EncodedMessage = serde_json::from_value(first.clone()).unwrap();
+    assert_eq!(message.decode().unwrap(), b"1000");
+
+    assert!(topic.publish_messages(Vec::new()).await.unwrap().is_empty());
+    assert_eq!(server.requests().len(), 3);
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use cloud_pubsub::reconcile::{Change, Manifest};
+use common::{FakeServer, Recorded};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Drops fields holding proto3 default values, which the service leaves out of its JSON.
+fn strip_defaults(value: &mut Value) {
+    if let Value::Object(fields) = value {
+        fields.values_mut().for_each(strip_defaults);
+        fields.retain(|_, value| match value {
+            Value::Bool(value) => *value,
+            Value::Number(value) => value.as_f64() != Some(0.0),
+            Value::String(value) => !value.is_empty(),
+            Value::Array(values) => !values.is_empty(),
+            Value::Object(fields) => !fields.is_empty(),
+            Value::Null => false,
+        });
+    }
+}
+
+/// Keeps topics and subscriptions in memory, filling in a default and dropping default
+/// values like the service does.
+fn admin_stub(request: &Recorded, resources: &Mutex<BTreeMap<String, Value>>) -> (u16, String) {
+    let mut resources = resources.lock().unwrap();
+    let path = request.uri.split('?').next().unwrap();
+    let name = path.trim_start_matches("/v1/").to_string();
+    match request.method.as_str() {
+        "GET" if name.ends_with("/topics") || name.ends_with("/subscriptions") => {
+            let collection = name.rsplit('/').next().unwrap();
+            let items: Vec<&Value> = resources
+                .iter()
+                .filter(|(key, _)| key.starts_with(&format!("{}/", name)))
+                .map(|(_, resource)| resource)
+                .collect();
+            (200, json!({ collection: items }).to_string())
+        }
+        "GET" => match resources.get(&name) {
+            Some(resource) => (200, resource.to_string()),
+            None => (
+                404,
+                r#"{"error":{"code":404,"status":"NOT_FOUND"}}"#.to_string(),
+            ),
+        },
+        "PUT" => {
+            let mut resource = request.json();
+            resource["name"] = json!(name);
+            if let Some(policy) = resource.get_mut("deadLetterPolicy") {
+                if policy.get("maxDeliveryAttempts").is_none() {
+                    policy["maxDeliveryAttempts"] = json!(5);
+                }
+            }
+            strip_defaults(&mut resource);
+            resources.insert(name, resource.clone());
+            (200, resource.to_string())
+        }
+        "PATCH" => {
+            let body = request.json();
+            let (_, update) = body.as_object().unwrap().iter().next().unwrap();
+            let resource = resources.get_mut(&name).unwrap();
+            for field in body["updateMask"].as_str().unwrap().split(',') {
+                resource[field] = update[field].clone();
+            }
+            strip_defaults(resource);
+            (200, resource.to_string())
+        }
+        _ => (405, String::new()),
+    }
+}
+
+fn manifest(ack_deadline_seconds: u32, filter: Option<&str>) -> Manifest {
+    let mut subscription = json!({
+        "name": "invoices-worker",
+        "topic": "invoices",
+        "ackDeadlineSeconds": ack_deadline_seconds,
+        "deadLetterPolicy": {"deadLetterTopic": "projects/test-project/topics/invoices-dead"},
+    });
+    if let Some(filter) = filter {
+        subscription["filter"] = json!(filter);
+    }
+    let manifest = json!({
+        "topics": [
+            {"name": "invoices", "messageRetentionDuration": "86400s"},
+            {"name": "invoices-dead"},
+        ],
+        "subscriptions": [subscription],
+    });
+    Manifest::from_json(&manifest.to_string()).unwrap()
+}
+
+fn summary(changes: &[Change]) -> Vec<String> {
+    changes
+        .iter()
+        .map(|change| match change {
+            Change::CreateTopic(spec) => format!("+topic {}", spec.name),
+            Change::UpdateTopic { spec, .. } => format!("~topic {}", spec.name),
+            Change::CreateSubscription(spec) => format!("+subscription {}", spec.name),
+            Change::UpdateSubscription { spec, drift } => format!(
+                "~subscription {} {}",
+                spec.name,
+                drift
+                    .iter()
+                    .map(|drift| drift.field.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Change::Conflict { name, drift } => format!(
+                "!subscription {} {}",
+                name,
+                drift
+                    .iter()
+                    .map(|drift| drift.field.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn plan_and_apply_converge() {
+    let resources = Mutex::new(BTreeMap::new());
+    let server = FakeServer::start(move |request| admin_stub(request, &resources));
+    let client = common::client(&server, "test-project");
+
+    let manifest = manifest(60, None);
+    let plan = client.plan(&manifest).await.unwrap();
+    assert_eq!(
+        summary(&plan.changes),
+        [
+            "+topic invoices",
+            "+topic invoices-dead",
+            "+subscription invoices-worker"
+        ]
+    );
+    client.apply(&plan).await.unwrap();
+
+    let created = client
+        .subscribe("invoices-worker")
+        .unwrap()
+        .get()
+        .await
+        .unwrap();
+    assert_eq!(
+        created.topic.unwrap().to_string(),
+        "projects/test-project/topics/invoices"
+    );
+    assert_eq!(created.config.ack_deadline_seconds, Some(60));
+
+    // Defaults filled in by the service are not drift.
+    assert!(client.plan(&manifest).await.unwrap().is_empty());
+
+    let manifest = self::manifest(30, Some("attributes.kind = \"paid\""));
+    let plan = client.plan(&manifest).await.unwrap();
+    assert_eq!(
+        summary(&plan.changes),
+        [
+            "!subscription invoices-worker filter",
+            "~subscription invoices-worker ackDeadlineSeconds"
+        ]
+    );
+    client.apply(&plan).await.unwrap();
+
+    let patch = server
+        .requests()
+        .into_iter()
+        .find(|request| request.method == "PATCH")
+        .unwrap();
+    assert_eq!(patch.json()["updateMask"], "ackDeadlineSeconds");
+
+    // Only the conflict, which apply skips, remains.
+    let plan = client.plan(&manifest).await.unwrap();
+    assert_eq!(
+        summary(&plan.changes),
+        ["!subscription invoices-worker filter"]
+    );
+}
+
+#[tokio::test]
+async fn default_values_are_not_drift() {
+    let resources = Mutex::new(BTreeMap::new());
+    let server = FakeServer::start(move |request| admin_stub(request, &resources));
+    let client = common::client(&server, "test-project");
+
+    let manifest = Manifest::from_json(
+        &json!({
+            "topics": [{"name": "orders"}],
+            "subscriptions": [{
+                "name": "orders-worker",
+                "topic": "orders",
+                "enableMessageOrdering": false,
+                "retainAckedMessages": false,
+                "filter": "",
+            }],
+        })
+        .to_string(),
+    )
+    .unwrap();
+    client
+        .apply(&client.plan(&manifest).await.unwrap())
+        .await
+        .unwrap();
+
+    let stored = client
+        .subscribe("orders-worker")
+        .unwrap()
+        .get()
+        .await
+        .unwrap();
+    assert_eq!(stored.config.enable_message_ordering, None);
+    assert!(client.plan(&manifest).await.unwrap().is_empty());
+}
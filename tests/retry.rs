@@ -0,0 +1,223 @@
+mod common;
+
+use cloud_pubsub::error::{Code, Error};
+use cloud_pubsub::{Client, EncodedMessage, FromPubSubMessage, RetryPolicy};
+use common::FakeServer;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug)]
+enum Call {
+    Publish,
+    Pull,
+    Acknowledge,
+    Admin,
+}
+
+struct Ignored;
+
+impl FromPubSubMessage for Ignored {
+    fn from(_: EncodedMessage) -> Result<Self, Error> {
+        Ok(Ignored)
+    }
+}
+
+/// A policy retrying almost immediately, so tests only wait where they mean to.
+fn fast_policy() -> RetryPolicy {
+    RetryPolicy::default()
+        .with_initial_backoff(Duration::from_millis(1))
+        .with_jitter(false)
+}
+
+async fn client(server: &FakeServer, policy: RetryPolicy) -> Client {
+    Client::builder()
+        .emulator(server.addr.to_string())
+        .project("test-project".to_string())
+        .retry_policy(policy)
+        .build()
+        .await
+        .unwrap()
+}
+
+fn error_body(http_status: u16, code: &str) -> String {
+    serde_json::json!({"error": {"code": http_status, "status": code, "message": "failed"}})
+        .to_string()
+}
+
+/// Makes `call`, which the server fails with `code` before succeeding, and returns how
+/// many requests it took.
+async fn attempts(call: Call, http_status: u16, code: &str) -> usize {
+    let failed = AtomicUsize::new(0);
+    let body = error_body(http_status, code);
+    let server = FakeServer::start(move |request| {
+        if failed.fetch_add(1, Ordering::SeqCst) == 0 {
+            return (http_status, body.clone());
+        }
+        let response = match request.uri.rsplit(':').next().unwrap() {
+            "publish" => r#"{"messageIds":["1"]}"#,
+            "pull" => r#"{"receivedMessages":[]}"#,
+            "acknowledge" => "{}",
+            _ => r#"{"name":"projects/test-project/topics/events"}"#,
+        };
+        (200, response.to_string())
+    });
+    let client = client(&server, fast_policy()).await;
+    let topic = client.topic("events").unwrap();
+    let subscription = client.subscribe("events-worker").unwrap();
+    match call {
+        Call::Publish => drop(topic.publish("hello").await),
+        Call::Pull => drop(subscription.get_messages::<Ignored>(10).await),
+        Call::Acknowledge => subscription.acknowledge_messages(vec!["ack".into()]).await,
+        Call::Admin => drop(topic.get().await),
+    }
+    server.requests().len()
+}
+
+async fn assert_retried(call: Call, retried: &[(u16, &str)], not_retried: &[(u16, &str)]) {
+    for (http_status, code) in retried {
+        assert_eq!(
+            attempts(call, *http_status, code).await,
+            2,
+            "{:?} {}",
+            call,
+            code
+        );
+    }
+    for (http_status, code) in not_retried {
+        assert_eq!(
+            attempts(call, *http_status, code).await,
+            1,
+            "{:?} {}",
+            call,
+            code
+        );
+    }
+}
+
+#[tokio::test]
+async fn publish_retries_transient_codes() {
+    assert_retried(
+        Call::Publish,
+        &[
+            (409, "ABORTED"),
+            (499, "CANCELLED"),
+            (500, "INTERNAL"),
+            (429, "RESOURCE_EXHAUSTED"),
+            (500, "UNKNOWN"),
+            (503, "UNAVAILABLE"),
+            (504, "DEADLINE_EXCEEDED"),
+        ],
+        &[
+            (400, "INVALID_ARGUMENT"),
+            (404, "NOT_FOUND"),
+            (403, "PERMISSION_DENIED"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn pull_retries_unknown_aborted_and_unavailable() {
+    assert_retried(
+        Call::Pull,
+        &[(500, "UNKNOWN"), (409, "ABORTED"), (503, "UNAVAILABLE")],
+        &[
+            (500, "INTERNAL"),
+            (429, "RESOURCE_EXHAUSTED"),
+            (404, "NOT_FOUND"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn acknowledge_retries_only_unavailable() {
+    assert_retried(
+        Call::Acknowledge,
+        &[(503, "UNAVAILABLE")],
+        &[(409, "ABORTED"), (500, "INTERNAL"), (500, "UNKNOWN")],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn admin_calls_retry_only_unavailable() {
+    assert_retried(
+        Call::Admin,
+        &[(503, "UNAVAILABLE")],
+        &[
+            (409, "ABORTED"),
+            (500, "INTERNAL"),
+            (504, "DEADLINE_EXCEEDED"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn attempts_are_capped() {
+    let server = FakeServer::start(|_| (503, error_body(503, "UNAVAILABLE")));
+    let client = client(&server, fast_policy().with_max_attempts(3)).await;
+
+    let err = client
+        .topic("events")
+        .unwrap()
+        .publish("hello")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status().unwrap().code, Code::Unavailable);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn retry_info_delay_is_honoured() {
+    let failed = AtomicUsize::new(0);
+    let server = FakeServer::start(move |_| {
+        if failed.fetch_add(1, Ordering::SeqCst) > 0 {
+            return (200, r#"{"messageIds":["1"]}"#.to_string());
+        }
+        let body = serde_json::json!({"error": {
+            "code": 429,
+            "status": "RESOURCE_EXHAUSTED",
+            "details": [{
+                "@type": "type.googleapis.com/google.rpc.RetryInfo",
+                "retryDelay": "0.3s",
+            }],
+        }});
+        (429, body.to_string())
+    });
+    let client = client(&server, fast_policy()).await;
+
+    let started = Instant::now();
+    client
+        .topic("events")
+        .unwrap()
+        .publish("hello")
+        .await
+        .unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn retries_stop_at_the_total_deadline() {
+    let server = FakeServer::start(|_| (503, error_body(503, "UNAVAILABLE")));
+    let policy = fast_policy()
+        .with_initial_backoff(Duration::from_millis(100))
+        .with_multiplier(1.0)
+        .with_max_attempts(100)
+        .with_total_deadline(Some(Duration::from_millis(350)));
+    let client = client(&server, policy).await;
+
+    let started = Instant::now();
+    let err = client
+        .topic("events")
+        .unwrap()
+        .publish("hello")
+        .await
+        .unwrap_err();
+    // The last error is returned rather than sleeping past the deadline.
+    assert!(started.elapsed() < Duration::from_millis(350));
+    assert_eq!(err.status().unwrap().code, Code::Unavailable);
+    assert!((3..=4).contains(&server.requests().len()));
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use cloud_pubsub::error::Error;
+use cloud_pubsub::{Client, EncodedMessage, FromPubSubMessage};
+use common::FakeServer;
+use std::time::{Duration, Instant};
+
+struct Ignored;
+
+impl FromPubSubMessage for Ignored {
+    fn from(_: EncodedMessage) -> Result<Self, Error> {
+        Ok(Ignored)
+    }
+}
+
+/// A server taking `delay` to answer every request successfully.
+fn slow_server(delay: Duration) -> FakeServer {
+    FakeServer::start_delayed(delay, |request| {
+        if request.uri.ends_with(":publish") {
+            (200, r#"{"messageIds":["1"]}"#.to_string())
+        } else {
+            (200, r#"{"receivedMessages":[]}"#.to_string())
+        }
+    })
+}
+
+async fn client(server: &FakeServer, timeout: Duration) -> Client {
+    Client::builder()
+        .emulator(server.addr.to_string())
+        .project("test-project".to_string())
+        .timeout(timeout)
+        .build()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn operation_fails_within_the_client_timeout_across_retries() {
+    let server = slow_server(Duration::from_secs(1));
+    let client = client(&server, Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    let err = client
+        .topic("events")
+        .unwrap()
+        .publish("hello")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Timeout(timeout) if timeout == Duration::from_millis(100)));
+    assert!(started.elapsed() < Duration::from_millis(300));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn topic_timeout_overrides_the_client_default() {
+    let server = slow_server(Duration::from_millis(300));
+    let client = client(&server, Duration::from_millis(100)).await;
+
+    let topic = client.topic("events").unwrap();
+    assert!(matches!(
+        topic.publish("hello").await,
+        Err(Error::Timeout(_))
+    ));
+    let response = topic
+        .with_timeout(Duration::from_secs(5))
+        .publish("hello")
+        .await
+        .unwrap();
+    assert_eq!(response.message_ids, ["1"]);
+}
+
+#[tokio::test]
+async fn subscription_timeout_overrides_the_client_default() {
+    let server = slow_server(Duration::from_secs(1));
+    let client = client(&server, Duration::from_secs(30)).await;
+
+    let started = Instant::now();
+    let result = client
+        .subscribe("events-worker")
+        .unwrap()
+        .with_timeout(Duration::from_millis(100))
+        .get_messages::<Ignored>(10)
+        .await;
+    assert!(matches!(result, Err(Error::Timeout(_))));
+    assert!(started.elapsed() < Duration::from_millis(300));
+}
@@ -0,0 +1,190 @@
+//! Runs against both TLS backends, using a rustls server with generated certificates.
+
+use cloud_pubsub::Client;
+use hyper::service::service_fn;
+use hyper::{Body, Response};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::extension::{BasicConstraints, ExtendedKeyUsage, SubjectAlternativeName};
+use openssl::x509::{X509Name, X509};
+use rustls::server::{AllowAnyAuthenticatedClient, NoClientAuth};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+fn key() -> PKey<Private> {
+    PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+}
+
+fn name(common_name: &str) -> X509Name {
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_text("CN", common_name).unwrap();
+    name.build()
+}
+
+/// A certificate for `common_name`, signed by `issuer` or self-signed as a CA.
+fn certificate(
+    common_name: &str,
+    key: &PKey<Private>,
+    issuer: Option<(&X509, &PKey<Private>)>,
+) -> X509 {
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(
+            &BigNum::from_u32(rand_serial())
+                .unwrap()
+                .to_asn1_integer()
+                .unwrap(),
+        )
+        .unwrap();
+    builder.set_subject_name(&name(common_name)).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    match issuer {
+        Some((issuer, issuer_key)) => {
+            builder.set_issuer_name(issuer.subject_name()).unwrap();
+            builder
+                .append_extension(
+                    ExtendedKeyUsage::new()
+                        .server_auth()
+                        .client_auth()
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+            let san = SubjectAlternativeName::new()
+                .dns("localhost")
+                .build(&builder.x509v3_context(Some(issuer), None))
+                .unwrap();
+            builder.append_extension(san).unwrap();
+            builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        }
+        None => {
+            builder.set_issuer_name(&name(common_name)).unwrap();
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+            builder.sign(key, MessageDigest::sha256()).unwrap();
+        }
+    }
+    builder.build()
+}
+
+fn rand_serial() -> u32 {
+    rand::random::<u32>() >> 1
+}
+
+/// Serves a publish response over TLS with a certificate for `localhost`, requiring a
+/// client certificate signed by `client_ca` when it is given.
+async fn start_server(cert: &X509, key: &PKey<Private>, client_ca: Option<&X509>) -> SocketAddr {
+    let verifier = match client_ca {
+        Some(client_ca) => {
+            let mut roots = rustls::RootCertStore::empty();
+            roots
+                .add(&rustls::Certificate(client_ca.to_der().unwrap()))
+                .unwrap();
+            AllowAnyAuthenticatedClient::new(roots).boxed()
+        }
+        None => NoClientAuth::boxed(),
+    };
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            vec![rustls::Certificate(cert.to_der().unwrap())],
+            rustls::PrivateKey(key.private_key_to_pkcs8().unwrap()),
+        )
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    let service = service_fn(|_| async {
+                        Ok::<_, Infallible>(Response::new(Body::from(r#"{"messageIds":["1"]}"#)))
+                    });
+                    let _ = hyper::server::conn::Http::new()
+                        .serve_connection(stream, service)
+                        .await;
+                }
+            });
+        }
+    });
+    addr
+}
+
+async fn publish(
+    addr: SocketAddr,
+    root_certificates: Option<Vec<u8>>,
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+) -> bool {
+    let mut builder = Client::builder()
+        .endpoint(format!("https://localhost:{}", addr.port()))
+        .project("test-project".to_string());
+    if let Some(pem) = root_certificates {
+        builder = builder.add_root_certificate(pem);
+    }
+    if let Some((cert_chain, private_key)) = identity {
+        builder = builder.identity(cert_chain, private_key);
+    }
+    let client = builder.build().await.unwrap();
+    client
+        .topic("events")
+        .unwrap()
+        .with_retry_policy(cloud_pubsub::RetryPolicy::never())
+        .publish("hello")
+        .await
+        .is_ok()
+}
+
+#[tokio::test]
+async fn trusts_every_certificate_of_a_root_bundle() {
+    let ca_key = key();
+    let ca = certificate("Test CA", &ca_key, None);
+    let unrelated_key = key();
+    let unrelated = certificate("Unrelated CA", &unrelated_key, None);
+    let server_key = key();
+    let server_cert = certificate("localhost", &server_key, Some((&ca, &ca_key)));
+    let addr = start_server(&server_cert, &server_key, None).await;
+
+    // The signing CA comes second, so it is only trusted if the whole bundle is read.
+    let mut bundle = unrelated.to_pem().unwrap();
+    bundle.extend(ca.to_pem().unwrap());
+
+    assert!(!publish(addr, None, None).await);
+    assert!(!publish(addr, Some(unrelated.to_pem().unwrap()), None).await);
+    assert!(publish(addr, Some(bundle), None).await);
+}
+
+#[tokio::test]
+async fn presents_the_client_identity() {
+    let ca_key = key();
+    let ca = certificate("Test CA", &ca_key, None);
+    let server_key = key();
+    let server_cert = certificate("localhost", &server_key, Some((&ca, &ca_key)));
+    let client_key = key();
+    let client_cert = certificate("client", &client_key, Some((&ca, &ca_key)));
+    let addr = start_server(&server_cert, &server_key, Some(&ca)).await;
+
+    let root = || Some(ca.to_pem().unwrap());
+    let identity = (
+        client_cert.to_pem().unwrap(),
+        client_key.private_key_to_pem_pkcs8().unwrap(),
+    );
+    assert!(!publish(addr, root(), None).await);
+    assert!(publish(addr, root(), Some(identity)).await);
+}